@@ -0,0 +1,308 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::memory_reader::MemoryReader;
+use crate::process::Module;
+
+/// Address given by an expression like `libgame.so+1A2B0`,
+/// `[[libgame.so+1A2B0]+18]+40` or `7ffd1000-8*4`, which can be
+/// resolved again after the process restarted.
+///
+/// ```text
+/// expr   := term (("+" | "-") term)*
+/// term   := factor ("*" factor)*
+/// factor := number | module | "[" expr "]" | "(" expr ")"
+/// ```
+///
+/// Numbers are in hex, with or without `0x`. Anything else is the
+/// file name of a module and stands for its start. Names that look
+/// like a number or contain operators can be put in double quotes.
+/// `[expr]` reads the pointer at expr.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressExpr {
+    Number(usize),
+    Module(String),
+    Deref(Box<AddressExpr>),
+    Add(Box<AddressExpr>, Box<AddressExpr>),
+    Sub(Box<AddressExpr>, Box<AddressExpr>),
+    Mul(Box<AddressExpr>, Box<AddressExpr>),
+}
+
+impl AddressExpr {
+    /// Evaluate the expression with modules loaded where given,
+    /// reading pointers with memory_reader
+    pub fn resolve<U: MemoryReader>(
+        &self,
+        modules: &[Module],
+        memory_reader: &mut U,
+    ) -> Result<usize, String> {
+        Ok(match self {
+            AddressExpr::Number(number) => *number,
+            AddressExpr::Module(name) => {
+                modules
+                    .iter()
+                    .find(|module| module.name == *name)
+                    .ok_or(format!("module {:?} isn't loaded", name))?
+                    .start
+            }
+            AddressExpr::Deref(expr) => {
+                let addr = expr.resolve(modules, memory_reader)?;
+                let mut pointer = [0; std::mem::size_of::<usize>()];
+                memory_reader
+                    .read_bytes(addr, &mut pointer)
+                    .map_err(|e| format!("couldn't read pointer at {:x}: {}", addr, e))?;
+                usize::from_le_bytes(pointer)
+            }
+            AddressExpr::Add(lhs, rhs) => lhs
+                .resolve(modules, memory_reader)?
+                .wrapping_add(rhs.resolve(modules, memory_reader)?),
+            AddressExpr::Sub(lhs, rhs) => lhs
+                .resolve(modules, memory_reader)?
+                .wrapping_sub(rhs.resolve(modules, memory_reader)?),
+            AddressExpr::Mul(lhs, rhs) => lhs
+                .resolve(modules, memory_reader)?
+                .wrapping_mul(rhs.resolve(modules, memory_reader)?),
+        })
+    }
+
+    /// Expression for addr relative to the module it's in, or just
+    /// addr if it isn't in any
+    pub fn relative_to_module(modules: &[Module], addr: usize) -> AddressExpr {
+        match Module::find(modules, addr) {
+            Some(module) => AddressExpr::Add(
+                Box::new(AddressExpr::Module(module.name.clone())),
+                Box::new(AddressExpr::Number(addr - module.start)),
+            ),
+            None => AddressExpr::Number(addr),
+        }
+    }
+}
+
+impl fmt::Display for AddressExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressExpr::Number(number) => write!(f, "{:x}", number),
+            AddressExpr::Module(name) => {
+                let plain = usize::from_str_radix(name.trim_start_matches("0x"), 16).is_err()
+                    && !name.contains(|c: char| c.is_whitespace() || "+-*[]()\"".contains(c));
+                match plain {
+                    true => write!(f, "{}", name),
+                    false => write!(f, "\"{}\"", name),
+                }
+            }
+            AddressExpr::Deref(expr) => write!(f, "[{}]", expr),
+            AddressExpr::Add(lhs, rhs) => write!(f, "{}+{}", lhs, rhs),
+            AddressExpr::Sub(lhs, rhs) => match **rhs {
+                AddressExpr::Add(..) | AddressExpr::Sub(..) => write!(f, "{}-({})", lhs, rhs),
+                _ => write!(f, "{}-{}", lhs, rhs),
+            },
+            AddressExpr::Mul(lhs, rhs) => {
+                // Sums in factors need their parentheses back
+                let factor = |f: &mut fmt::Formatter, expr: &AddressExpr| match expr {
+                    AddressExpr::Add(..) | AddressExpr::Sub(..) => write!(f, "({})", expr),
+                    _ => write!(f, "{}", expr),
+                };
+                factor(f, lhs)?;
+                write!(f, "*")?;
+                factor(f, rhs)
+            }
+        }
+    }
+}
+
+impl FromStr for AddressExpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = AddressParser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.parse_expr()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {}", token)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Symbol(char),
+    Word(String),
+    Quoted(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Symbol(symbol) => write!(f, "{:?}", symbol),
+            Token::Word(word) => write!(f, "{:?}", word),
+            Token::Quoted(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+const SYMBOLS: &str = "+-*[]()";
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if SYMBOLS.contains(c) {
+            tokens.push(Token::Symbol(c));
+            rest = &rest[1..];
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or("missing closing quote")?;
+            tokens.push(Token::Quoted(quoted[..end].to_string()));
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || SYMBOLS.contains(c) || c == '"')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct AddressParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl AddressParser {
+    fn peek_symbol(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(symbol)) => Some(*symbol),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(found)) if *found == symbol => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(token) => Err(format!("expected {:?}, found {}", symbol, token)),
+            None => Err(format!("expected {:?}", symbol)),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<AddressExpr, String> {
+        let mut expr = self.parse_term()?;
+        while let Some(symbol @ ('+' | '-')) = self.peek_symbol() {
+            self.pos += 1;
+            let rhs = Box::new(self.parse_term()?);
+            expr = match symbol {
+                '+' => AddressExpr::Add(Box::new(expr), rhs),
+                _ => AddressExpr::Sub(Box::new(expr), rhs),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn parse_term(&mut self) -> Result<AddressExpr, String> {
+        let mut expr = self.parse_factor()?;
+        while self.peek_symbol() == Some('*') {
+            self.pos += 1;
+            expr = AddressExpr::Mul(Box::new(expr), Box::new(self.parse_factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_factor(&mut self) -> Result<AddressExpr, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("expected an address")?;
+        self.pos += 1;
+        match token {
+            Token::Symbol('[') => {
+                let expr = self.parse_expr()?;
+                self.expect(']')?;
+                Ok(AddressExpr::Deref(Box::new(expr)))
+            }
+            Token::Symbol('(') => {
+                let expr = self.parse_expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Token::Word(word) => {
+                let digits = word.strip_prefix("0x").unwrap_or(&word);
+                Ok(match usize::from_str_radix(digits, 16) {
+                    Ok(number) => AddressExpr::Number(number),
+                    Err(_) => AddressExpr::Module(word),
+                })
+            }
+            Token::Quoted(name) => Ok(AddressExpr::Module(name)),
+            Token::Symbol(_) => Err(format!("unexpected {}", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_reader::MemoryReaderSimple;
+    use crate::process::Process;
+    use std::process;
+
+    #[test]
+    fn address_expr_parse() {
+        let expr: AddressExpr = "[[libgame.so + 0x1A2B0] + 18] + 40".parse().unwrap();
+        assert_eq!(expr.to_string(), "[[libgame.so+1a2b0]+18]+40");
+        let expr: AddressExpr = "(10 - 8) * 4 + \"my game\"".parse().unwrap();
+        assert_eq!(expr.to_string(), "(10-8)*4+\"my game\"");
+        let expr: AddressExpr = "10 - (8 - 4)".parse().unwrap();
+        assert_eq!(expr.to_string(), "10-(8-4)");
+        assert_eq!(
+            "\"beef\"".parse::<AddressExpr>().unwrap().to_string(),
+            "\"beef\""
+        );
+        assert!("[10".parse::<AddressExpr>().is_err());
+        assert!("10 +".parse::<AddressExpr>().is_err());
+        assert!("10 20".parse::<AddressExpr>().is_err());
+        assert!("\"game".parse::<AddressExpr>().is_err());
+    }
+
+    #[test]
+    fn address_expr_resolve() {
+        let target = Box::new(0u64);
+        let pointer = Box::new(&*target as *const u64 as usize - 0x10);
+        let pointer_addr = &*pointer as *const usize as usize;
+        let process = Process::try_new(process::id()).unwrap();
+        let mut memory_reader = MemoryReaderSimple::new(&process);
+        let modules = vec![Module {
+            name: "libgame.so".to_string(),
+            start: pointer_addr - 0x100,
+            end: pointer_addr + 0x100,
+        }];
+
+        let expr: AddressExpr = "[libgame.so + 80 * 2] + 10".parse().unwrap();
+        let addr = expr.resolve(&modules, &mut memory_reader).unwrap();
+        assert_eq!(addr, &*target as *const u64 as usize);
+        assert!("[0]"
+            .parse::<AddressExpr>()
+            .unwrap()
+            .resolve(&modules, &mut memory_reader)
+            .is_err());
+        let unknown = "libother.so+10".parse::<AddressExpr>().unwrap();
+        assert!(unknown.resolve(&modules, &mut memory_reader).is_err());
+
+        let relative = AddressExpr::relative_to_module(&modules, pointer_addr);
+        assert_eq!(relative.to_string(), "libgame.so+100");
+        assert_eq!(
+            relative.resolve(&modules, &mut memory_reader),
+            Ok(pointer_addr)
+        );
+        assert_eq!(
+            AddressExpr::relative_to_module(&modules, 0x10).to_string(),
+            "10"
+        );
+    }
+}
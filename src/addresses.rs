@@ -1,32 +1,68 @@
 use std::fmt::Debug;
-use std::iter;
 use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use std::{any, str::FromStr};
 
+use crate::commands::ValType;
 use crate::context::Context;
 use crate::memory_map::MemoryMap;
-use crate::memory_reader::{FromLeBytes, MemoryReader, MemoryReaderSimple};
+use crate::memory_reader::{FromLeBytes, MemoryReader, ToLeBytes};
 use crate::process::Process;
+use crate::val_type::with_val_type;
 
-pub trait Addresses {
+/// Maps bigger than this are split in parts scanned by separate
+/// threads during the initial scan
+pub(crate) const PART_SIZE: usize = 64 * 1024 * 1024;
+/// How many addresses a thread goes through during the non-initial
+/// scan before it reports progress
+pub(crate) const PROGRESS_BATCH: usize = 64 * 1024;
+/// Error for listing the results of an unknown scan, which only
+/// copied the memory
+pub(crate) const UNFILTERED_ERROR: &str =
+    "filter the unknown scan first, it only took a snapshot of the memory";
+
+pub trait Addresses: Send + Sync {
     fn new(process: &Process) -> Self
     where
         Self: Sized;
     fn get_type(&self) -> String;
+    /// Size in bytes of each found value
+    fn value_size(&self) -> usize;
+    /// Number of scans done so far
+    fn passes(&self) -> usize;
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
+    /// Filter the addresses with the expression, or find them in the
+    /// whole memory on the first scan. Relative operators compare
+    /// against the values from baseline. Returns how many addresses
+    /// got dropped because they couldn't be read anymore
     fn scan(
         &mut self,
         ctx: &Context,
         expr: &ScanExpr,
+        baseline: &Baseline,
         report_progress: Box<dyn FnMut(usize, usize)>,
-    );
-    fn get_addrs(&self) -> Vec<usize>;
-    fn clone_box(&self) -> Box<dyn Addresses>;
-    fn get_vals(&self) -> Vec<String>;
-    // address, value when scanned, current value
-    fn get_vals_to_print(&mut self) -> Vec<(usize, String, String)>;
-    fn write(&mut self, value: i32, addr_idx: usize);
+    ) -> Result<usize, String>;
+    /// Save the current values of the found addresses under name, so
+    /// later scans can use them as a baseline
+    fn snapshot(&mut self, name: &str) -> Result<(), String>;
+    fn snapshot_names(&self) -> Vec<String>;
+    fn get_addrs(&self) -> Result<Vec<usize>, String>;
+    /// Address with the given index, without going through all of
+    /// them when that can be avoided
+    fn get_addr(&self, idx: usize) -> Result<usize, String>;
+    /// Copy of the result set, e.g. to keep it for undo
+    fn try_clone_box(&self) -> Result<Box<dyn Addresses>, String>;
+    fn get_vals(&self) -> Result<Vec<String>, String>;
+    // address, value when scanned, current value of at most the first
+    // max addresses
+    fn get_vals_to_print(&mut self, max: usize) -> Result<Vec<(usize, String, String)>, String>;
+    /// Parse value as the scanned type and write it to the address
+    /// with the given index
+    fn write(&mut self, value: &str, addr_idx: usize) -> Result<(), String>;
 }
 
 #[derive(Debug)]
@@ -35,79 +71,431 @@ pub enum ScanExpr {
     LessEqual(String),
     Greater(String),
     GreaterEqual(String),
-    Equal(String),
-    NotEqual(String),
+    Equal(String, FloatCmp),
+    NotEqual(String, FloatCmp),
+    /// Between lower and upper bound
+    Between(String, String, Bounds),
+    NotBetween(String, String, Bounds),
     Changed,
     NotChanged,
+    Increased,
+    Decreased,
+    IncreasedBy(String, FloatCmp),
+    DecreasedBy(String, FloatCmp),
+    /// Increased by at least the given percentage
+    IncreasedByPercent(String),
+    /// Decreased by at least the given percentage
+    DecreasedByPercent(String),
+    And(Box<ScanExpr>, Box<ScanExpr>),
+    Or(Box<ScanExpr>, Box<ScanExpr>),
+    Not(Box<ScanExpr>),
     Refresh,
     Unknown,
 }
 
+/// Whether the bounds of `ScanExpr::Between` are part of the range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Bounds {
+    #[default]
+    Inclusive,
+    Exclusive,
+}
+
+impl Bounds {
+    #[inline(always)]
+    fn contains<T: PartialOrd>(self, lower: T, upper: T, val: T) -> bool {
+        match self {
+            Bounds::Inclusive => lower <= val && val <= upper,
+            Bounds::Exclusive => lower < val && val < upper,
+        }
+    }
+}
+
+/// Values relative operators like `changed` compare against
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Baseline {
+    /// Values from the last pass
+    #[default]
+    Previous,
+    /// Values from the initial scan
+    First,
+    /// Values from the nth pass, counting from 1
+    Pass(usize),
+    /// Values saved with `snapshot`
+    Snapshot(String),
+}
+
+impl Baseline {
+    /// Whether it's the same as the values from the last pass, given
+    /// the number of passes done so far
+    pub fn is_previous(&self, passes: usize) -> bool {
+        match self {
+            Baseline::Previous => true,
+            Baseline::First => passes <= 1,
+            Baseline::Pass(pass) => *pass == passes,
+            Baseline::Snapshot(_) => false,
+        }
+    }
+}
+
+/// How == and != treat floating-point values. Integers always
+/// compare exactly no matter what's chosen here.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FloatCmp {
+    Exact,
+    /// Match when the difference is at most epsilon
+    Epsilon(f64),
+    /// Match when the value rounded to the operand's amount of
+    /// decimal places is equal to the operand, so `100.0` matches
+    /// 99.99998 but `100.00` doesn't match 99.99
+    #[default]
+    Rounded,
+    /// Like `Rounded` but the value is truncated (towards zero)
+    Truncated,
+    /// Match anything closer than one unit of the operand's last
+    /// decimal place, e.g. `100` matches everything in (99, 101)
+    ExtremeRounded,
+}
+
+impl FloatCmp {
+    /// Number of digits after the decimal point in the operand as
+    /// typed by the user, counting those an exponent moves there, so
+    /// `1.5e-3` has 4. Rounding modes use it to decide how precise the
+    /// comparison should be.
+    fn decimals(operand: &str) -> i32 {
+        let (mantissa, exponent) = operand.split_once(['e', 'E']).unwrap_or((operand, "0"));
+        let frac = mantissa.split_once('.').map_or(0, |(_, frac)| frac.len() as i32);
+        frac.saturating_sub(exponent.parse::<i32>().unwrap_or(0)).max(0)
+    }
+}
+
+/// Equality used by `ScanExpr::Equal` and `ScanExpr::NotEqual`
+pub trait ApproxEq: Copy + PartialEq {
+    fn approx_eq(self, operand: Self, cmp: FloatCmp, decimals: i32) -> bool;
+}
+
+macro_rules! impl_approx_eq_exact {
+    ($type:ty) => {
+        impl ApproxEq for $type {
+            #[inline(always)]
+            fn approx_eq(self, operand: Self, _cmp: FloatCmp, _decimals: i32) -> bool {
+                self == operand
+            }
+        }
+    };
+}
+
+macro_rules! impl_approx_eq_float {
+    ($type:ty) => {
+        impl ApproxEq for $type {
+            #[inline(always)]
+            fn approx_eq(self, operand: Self, cmp: FloatCmp, decimals: i32) -> bool {
+                let (val, operand) = (self as f64, operand as f64);
+                let unit = 10f64.powi(-decimals);
+                match cmp {
+                    FloatCmp::Exact => val == operand,
+                    FloatCmp::Epsilon(epsilon) => (val - operand).abs() <= epsilon,
+                    FloatCmp::Rounded => {
+                        operand - unit / 2.0 <= val && val < operand + unit / 2.0
+                    }
+                    FloatCmp::Truncated if operand >= 0.0 => {
+                        operand <= val && val < operand + unit
+                    }
+                    FloatCmp::Truncated => operand - unit < val && val <= operand,
+                    FloatCmp::ExtremeRounded => {
+                        operand - unit < val && val < operand + unit
+                    }
+                }
+            }
+        }
+    };
+}
+
+impl_approx_eq_exact!(i128);
+impl_approx_eq_exact!(u128);
+impl_approx_eq_exact!(i64);
+impl_approx_eq_exact!(u64);
+impl_approx_eq_exact!(i32);
+impl_approx_eq_exact!(u32);
+impl_approx_eq_exact!(i16);
+impl_approx_eq_exact!(u16);
+impl_approx_eq_exact!(i8);
+impl_approx_eq_exact!(u8);
+impl_approx_eq_float!(f64);
+impl_approx_eq_float!(f32);
+
+/// Arithmetic needed for comparing a value with its previous one
+pub trait Delta: Copy {
+    /// self - other, None for integers when it doesn't fit in the
+    /// type. Like `Increased` and `Decreased`, an i16 going from 32767
+    /// to -32768 decreased, it didn't increase by 1.
+    fn delta(self, other: Self) -> Option<Self>;
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! impl_delta_int {
+    ($type:ty) => {
+        impl Delta for $type {
+            #[inline(always)]
+            fn delta(self, other: Self) -> Option<Self> {
+                self.checked_sub(other)
+            }
+
+            #[inline(always)]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+macro_rules! impl_delta_float {
+    ($type:ty) => {
+        impl Delta for $type {
+            #[inline(always)]
+            fn delta(self, other: Self) -> Option<Self> {
+                Some(self - other)
+            }
+
+            #[inline(always)]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+impl_delta_int!(i128);
+impl_delta_int!(u128);
+impl_delta_int!(i64);
+impl_delta_int!(u64);
+impl_delta_int!(i32);
+impl_delta_int!(u32);
+impl_delta_int!(i16);
+impl_delta_int!(u16);
+impl_delta_int!(i8);
+impl_delta_int!(u8);
+impl_delta_float!(f64);
+impl_delta_float!(f32);
+
+/// Change from old to new in percent of old's magnitude. Any change
+/// from 0 is infinitely many percent, so there's none.
+fn percent_change<T: Delta>(new: T, old: T) -> Option<f64> {
+    let old = old.to_f64();
+    (old != 0.0).then(|| (new.to_f64() - old) / old.abs() * 100.0)
+}
+
+fn parse_operand<T>(operand: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Debug,
+{
+    operand
+        .parse::<T>()
+        .map_err(|e| format!("couldn't parse {:?}: {:?}", operand, e))
+}
+
+fn check_operands_as<T>(expr: &ScanExpr) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Debug,
+{
+    match expr {
+        ScanExpr::Less(operand)
+        | ScanExpr::LessEqual(operand)
+        | ScanExpr::Greater(operand)
+        | ScanExpr::GreaterEqual(operand)
+        | ScanExpr::Equal(operand, _)
+        | ScanExpr::NotEqual(operand, _)
+        | ScanExpr::IncreasedBy(operand, _)
+        | ScanExpr::DecreasedBy(operand, _) => parse_operand::<T>(operand).map(|_| ()),
+        ScanExpr::Between(lower, upper, _) | ScanExpr::NotBetween(lower, upper, _) => {
+            parse_operand::<T>(lower)?;
+            parse_operand::<T>(upper).map(|_| ())
+        }
+        ScanExpr::IncreasedByPercent(operand) | ScanExpr::DecreasedByPercent(operand) => {
+            parse_operand::<f64>(operand).map(|_| ())
+        }
+        ScanExpr::And(lhs, rhs) | ScanExpr::Or(lhs, rhs) => {
+            check_operands_as::<T>(lhs)?;
+            check_operands_as::<T>(rhs)
+        }
+        ScanExpr::Not(expr) => check_operands_as::<T>(expr),
+        ScanExpr::Changed
+        | ScanExpr::NotChanged
+        | ScanExpr::Increased
+        | ScanExpr::Decreased
+        | ScanExpr::Refresh
+        | ScanExpr::Unknown => Ok(()),
+    }
+}
+
+/// Match on a `ScanExpr` and bind `$pred` to a closure that takes a
+/// pair of (current value, previous value) and tells whether the
+/// expression holds for it, then evaluate `$body`. Every leaf gets
+/// its own monomorphized closure so scanning with a simple expression
+/// stays a tight loop. Only the operands of `And`, `Or` and `Not` are
+/// boxed.
+macro_rules! with_predicate {
+    ($expr:expr, $T:ty, $pred:ident => $body:expr) => {
+        match $expr {
+            ScanExpr::Equal(operand, cmp) => {
+                let decimals = FloatCmp::decimals(operand);
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| val.approx_eq(operand, *cmp, decimals);
+                $body
+            }
+            ScanExpr::NotEqual(operand, cmp) => {
+                let decimals = FloatCmp::decimals(operand);
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| !val.approx_eq(operand, *cmp, decimals);
+                $body
+            }
+            ScanExpr::Less(operand) => {
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| val < operand;
+                $body
+            }
+            ScanExpr::LessEqual(operand) => {
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| val <= operand;
+                $body
+            }
+            ScanExpr::Greater(operand) => {
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| val > operand;
+                $body
+            }
+            ScanExpr::GreaterEqual(operand) => {
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| val >= operand;
+                $body
+            }
+            ScanExpr::Between(lower, upper, bounds) => {
+                let lower = lower.parse::<$T>().unwrap();
+                let upper = upper.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| bounds.contains(lower, upper, val);
+                $body
+            }
+            ScanExpr::NotBetween(lower, upper, bounds) => {
+                let lower = lower.parse::<$T>().unwrap();
+                let upper = upper.parse::<$T>().unwrap();
+                let $pred = move |(val, _): ($T, $T)| !bounds.contains(lower, upper, val);
+                $body
+            }
+            ScanExpr::Changed => {
+                let $pred = |(val, old_val): ($T, $T)| val != old_val;
+                $body
+            }
+            ScanExpr::NotChanged => {
+                let $pred = |(val, old_val): ($T, $T)| val == old_val;
+                $body
+            }
+            ScanExpr::Increased => {
+                let $pred = |(val, old_val): ($T, $T)| val > old_val;
+                $body
+            }
+            ScanExpr::Decreased => {
+                let $pred = |(val, old_val): ($T, $T)| val < old_val;
+                $body
+            }
+            ScanExpr::IncreasedBy(operand, cmp) => {
+                let decimals = FloatCmp::decimals(operand);
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, old_val): ($T, $T)| {
+                    val.delta(old_val)
+                        .is_some_and(|delta| delta.approx_eq(operand, *cmp, decimals))
+                };
+                $body
+            }
+            ScanExpr::DecreasedBy(operand, cmp) => {
+                let decimals = FloatCmp::decimals(operand);
+                let operand = operand.parse::<$T>().unwrap();
+                let $pred = move |(val, old_val): ($T, $T)| {
+                    old_val
+                        .delta(val)
+                        .is_some_and(|delta| delta.approx_eq(operand, *cmp, decimals))
+                };
+                $body
+            }
+            ScanExpr::IncreasedByPercent(operand) => {
+                let operand = operand.parse::<f64>().unwrap();
+                let $pred = move |(val, old_val): ($T, $T)| {
+                    percent_change(val, old_val).is_some_and(|change| change >= operand)
+                };
+                $body
+            }
+            ScanExpr::DecreasedByPercent(operand) => {
+                let operand = operand.parse::<f64>().unwrap();
+                let $pred = move |(val, old_val): ($T, $T)| {
+                    percent_change(val, old_val).is_some_and(|change| -change >= operand)
+                };
+                $body
+            }
+            ScanExpr::And(lhs, rhs) => {
+                let (lhs, rhs) = (lhs.predicate::<$T>(), rhs.predicate::<$T>());
+                let $pred = move |vals: ($T, $T)| lhs(vals) && rhs(vals);
+                $body
+            }
+            ScanExpr::Or(lhs, rhs) => {
+                let (lhs, rhs) = (lhs.predicate::<$T>(), rhs.predicate::<$T>());
+                let $pred = move |vals: ($T, $T)| lhs(vals) || rhs(vals);
+                $body
+            }
+            ScanExpr::Not(expr) => {
+                let expr = expr.predicate::<$T>();
+                let $pred = move |vals: ($T, $T)| !expr(vals);
+                $body
+            }
+            ScanExpr::Refresh | ScanExpr::Unknown => {
+                let $pred = |_: ($T, $T)| true;
+                $body
+            }
+        }
+    };
+}
+
 impl ScanExpr {
-    /// Evaluate our expression with every argument from vals. When
-    /// the expression is true execute function f_if_true. Typically
-    /// we want the function to add filtered values to some other
-    /// container
+    /// Evaluate our expression with every argument from vals. Each of
+    /// them is a pair of the current value and the value from the
+    /// previous scan (on the first scan both are the same). When the
+    /// expression is true execute function f_if_true with the current
+    /// value. Typically we want the function to add filtered values
+    /// to some other container
     pub fn eval_expr<F, T, ValIter, AddrIter>(
         &self,
-        ctx: &Context,
         f_if_true: &mut F,
         vals: ValIter,
         addrs: AddrIter,
     ) where
         F: FnMut(T, usize),
-        T: FromStr + Copy + PartialOrd + PartialEq + Debug + FromLeBytes,
+        T: FromStr + Copy + PartialOrd + ApproxEq + Delta + Debug + FromLeBytes,
         T::Err: Debug,
-        ValIter: Iterator<Item = T>,
+        ValIter: Iterator<Item = (T, T)>,
         AddrIter: Iterator<Item = usize>,
         [(); mem::size_of::<T>()]:,
     {
-        match self {
-            Self::Equal(operand) => {
-                let operand = operand.parse::<T>().unwrap();
-                let mut f_expr = |val, _| val == operand;
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::NotEqual(operand) => {
-                let operand = operand.parse::<T>().unwrap();
-                let mut f_expr = |val, _| val != operand;
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::Less(operand) => {
-                let operand = operand.parse::<T>().unwrap();
-                let mut f_expr = |val, _| val < operand;
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::LessEqual(operand) => {
-                let operand = operand.parse::<T>().unwrap();
-                let mut f_expr = |val, _| val <= operand;
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::Greater(operand) => {
-                let operand = operand.parse::<T>().unwrap();
-                let mut f_expr = |val, _| val > operand;
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::GreaterEqual(operand) => {
-                let operand = operand.parse::<T>().unwrap();
-                let mut f_expr = |val, _| val >= operand;
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::Changed => {
-                let mut mem_reader = MemoryReaderSimple::new(ctx.process.as_ref().unwrap());
-                let mut f_expr = move |val, addr| val != mem_reader.read(addr);
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::NotChanged => {
-                let mut mem_reader = MemoryReaderSimple::new(ctx.process.as_ref().unwrap());
-                let mut f_expr = move |val, addr| val == mem_reader.read(addr);
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-            Self::Refresh | Self::Unknown => {
-                let mut f_expr = move |_, _| true;
-                Self::loop_over(f_if_true, &mut f_expr, vals, addrs);
-            }
-        }
+        with_predicate!(self, T, f_expr => {
+            let mut f_expr = f_expr;
+            Self::loop_over(f_if_true, &mut f_expr, vals, addrs)
+        })
+    }
+
+    /// Make sure every operand parses as a value of val_type, and
+    /// percentages as a number, so scanning with the expression can't
+    /// fail halfway through. Byte patterns are parsed by their scans.
+    pub fn check_operands(&self, val_type: ValType) -> Result<(), String> {
+        with_val_type!(val_type, check_operands_as(self), bytes => Ok(()))
+    }
+
+    /// The expression as a boxed closure, used for the operands of
+    /// compound expressions
+    fn predicate<'a, T>(&'a self) -> Box<dyn Fn((T, T)) -> bool + 'a>
+    where
+        T: 'a + FromStr + Copy + PartialOrd + ApproxEq + Delta + Debug + FromLeBytes,
+        T::Err: Debug,
+    {
+        with_predicate!(self, T, f_expr => Box::new(f_expr))
     }
 
     fn loop_over<F, FExpr, T, ValIter, AddrIter>(
@@ -117,14 +505,14 @@ impl ScanExpr {
         addrs: AddrIter,
     ) where
         F: FnMut(T, usize),
-        FExpr: FnMut(T, usize) -> bool,
+        FExpr: FnMut((T, T)) -> bool,
         T: Copy + Debug,
-        ValIter: Iterator<Item = T>,
+        ValIter: Iterator<Item = (T, T)>,
         AddrIter: Iterator<Item = usize>,
     {
         vals.zip(addrs)
-            .filter(|(val, addr)| f_expr(*val, *addr))
-            .for_each(|(val, addr)| f_if_true(val, addr));
+            .filter(|(vals, _)| f_expr(*vals))
+            .for_each(|((val, _), addr)| f_if_true(val, addr));
     }
 }
 
@@ -140,12 +528,46 @@ where
 {
     values: Vec<T>,
     addresses: Vec<usize>,
+    /// Number of scans done so far
+    passes: usize,
+    /// Values from passes before the last one, oldest first. Like the
+    /// snapshots they're kept parallel to `addresses`, so values of
+    /// filtered out addresses are removed from them as well.
+    earlier_values: Vec<Vec<T>>,
+    snapshots: Vec<(String, Vec<T>)>,
+    /// Raw copies of the memory taken by an initial scan for unknown
+    /// values. While there are any, `values` and `addresses` are
+    /// empty and the first filter builds them out of the regions.
+    regions: Arc<Vec<RegionSnapshot>>,
+    /// Distance between addresses in the regions
+    region_step: usize,
+    /// Number of addresses in the regions
+    region_len: usize,
     memory_reader: U,
 }
 
+/// Bytes of readable memory starting at addr. Usually a whole memory
+/// map, unless some of its pages couldn't be read.
+#[derive(Debug)]
+struct RegionSnapshot {
+    addr: usize,
+    bytes: Vec<u8>,
+}
+
 impl<T, U> Addresses for AddrsSimple<T, U>
 where
-    T: FromLeBytes + Debug + FromStr + Copy + PartialOrd + PartialEq + ToString + 'static,
+    T: FromLeBytes
+        + ToLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + ToString
+        + Send
+        + Sync
+        + 'static,
     T::Err: Debug,
     U: MemoryReader + 'static,
     [(); mem::size_of::<T>()]:,
@@ -154,103 +576,271 @@ where
         Self {
             values: Vec::new(),
             addresses: Vec::new(),
+            passes: 0,
+            earlier_values: Vec::new(),
+            snapshots: Vec::new(),
+            regions: Arc::new(Vec::new()),
+            region_step: 1,
+            region_len: 0,
             memory_reader: U::new(process),
         }
     }
 
-    fn get_vals_to_print(&mut self) -> Vec<(usize, String, String)> {
-        self.addresses
+    fn get_vals_to_print(&mut self, max: usize) -> Result<Vec<(usize, String, String)>, String> {
+        self.check_filtered()?;
+        Ok(self
+            .addresses
             .iter()
             .zip(self.values.iter())
+            .take(max)
             .map(|(&addr, val)| {
-                (
-                    addr,
-                    val.to_string(),
-                    self.memory_reader.read(addr).to_string(),
-                )
+                let current = match self.memory_reader.try_read::<T>(addr) {
+                    Some(current) => current.to_string(),
+                    None => "??".to_string(),
+                };
+                (addr, val.to_string(), current)
             })
-            .collect()
+            .collect())
     }
 
-    fn clone_box(&self) -> Box<dyn Addresses> {
-        Box::new(Self {
+    fn try_clone_box(&self) -> Result<Box<dyn Addresses>, String> {
+        Ok(Box::new(Self {
             values: self.values.clone(),
             addresses: self.addresses.clone(),
+            passes: self.passes,
+            earlier_values: self.earlier_values.clone(),
+            snapshots: self.snapshots.clone(),
+            regions: Arc::clone(&self.regions),
+            region_step: self.region_step,
+            region_len: self.region_len,
             memory_reader: self.memory_reader.clone(),
-        })
+        }))
     }
-    fn get_addrs(&self) -> Vec<usize> {
-        self.addresses.clone()
+    fn get_addrs(&self) -> Result<Vec<usize>, String> {
+        self.check_filtered()?;
+        Ok(self.addresses.clone())
+    }
+
+    fn get_addr(&self, idx: usize) -> Result<usize, String> {
+        self.check_filtered()?;
+        self.addresses
+            .get(idx)
+            .copied()
+            .ok_or(format!("there is no address with index {}", idx))
     }
 
-    fn get_vals(&self) -> Vec<String> {
-        self.values.iter().map(|v| v.to_string()).collect()
+    fn get_vals(&self) -> Result<Vec<String>, String> {
+        self.check_filtered()?;
+        Ok(self.values.iter().map(|v| v.to_string()).collect())
     }
 
     fn get_type(&self) -> String {
         any::type_name::<T>().to_string()
     }
 
+    fn value_size(&self) -> usize {
+        mem::size_of::<T>()
+    }
+
+    fn passes(&self) -> usize {
+        self.passes
+    }
+
     fn len(&self) -> usize {
-        self.values.len()
+        self.values.len() + self.region_len
     }
 
     fn is_empty(&self) -> bool {
-        self.values.is_empty()
+        self.len() == 0
     }
 
     fn scan(
         &mut self,
         ctx: &Context,
         expr: &ScanExpr,
+        baseline: &Baseline,
         report_progress: Box<dyn FnMut(usize, usize)>,
-    ) {
-        if !self.values.is_empty() {
-            self.noninitial_scan(ctx, expr, report_progress);
+    ) -> Result<usize, String> {
+        let dropped = if !self.regions.is_empty() {
+            self.region_scan(ctx, expr, baseline, report_progress)?
+        } else if self.passes > 0 {
+            self.noninitial_scan(ctx, expr, baseline, report_progress)?
         } else {
-            self.initial_scan(ctx, expr, report_progress);
+            match baseline {
+                Baseline::Previous | Baseline::First => (),
+                _ => return Err("there is nothing to compare to before the first scan".to_string()),
+            }
+            self.initial_scan(ctx, expr, report_progress)
+        };
+        self.passes += 1;
+        Ok(dropped)
+    }
+
+    fn snapshot(&mut self, name: &str) -> Result<(), String> {
+        if self.passes == 0 {
+            return Err("there is nothing to snapshot before the first scan".to_string());
+        }
+        if !self.regions.is_empty() {
+            return Err("unknown values have to be filtered before taking a snapshot".to_string());
+        }
+        // Unreadable addresses keep the value they had when scanned
+        let values = self
+            .addresses
+            .iter()
+            .zip(self.values.iter())
+            .map(|(&addr, &val)| self.memory_reader.try_read::<T>(addr).unwrap_or(val))
+            .collect();
+        match self.snapshots.iter_mut().find(|(n, _)| n == name) {
+            Some((_, old)) => *old = values,
+            None => self.snapshots.push((name.to_string(), values)),
         }
+        Ok(())
     }
 
-    fn write(&mut self, value: i32, addr_idx: usize) {
-        let addr = self.addresses[addr_idx];
-        self.memory_reader.write(addr, value);
+    fn snapshot_names(&self) -> Vec<String> {
+        self.snapshots.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    fn write(&mut self, value: &str, addr_idx: usize) -> Result<(), String> {
+        let addr = self.get_addr(addr_idx)?;
+        let value = value
+            .parse::<T>()
+            .map_err(|e| format!("couldn't parse {:?}: {:?}", value, e))?;
+        self.memory_reader
+            .write(addr, value)
+            .map_err(|e| e.to_string())
     }
 }
 
 impl<T, U> AddrsSimple<T, U>
 where
-    T: FromLeBytes + Debug + FromStr + Copy + PartialOrd + PartialEq + 'static,
+    T: FromLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + Send
+        + Sync
+        + 'static,
     T::Err: Debug,
     U: MemoryReader + 'static,
     [(); mem::size_of::<T>()]:,
 {
+    /// The addresses aren't known while only the regions of an
+    /// unknown scan are held
+    fn check_filtered(&self) -> Result<(), String> {
+        match self.regions.is_empty() {
+            true => Ok(()),
+            false => Err(UNFILTERED_ERROR.to_string()),
+        }
+    }
+
     fn noninitial_scan(
         &mut self,
         ctx: &Context,
         expr: &ScanExpr,
+        baseline: &Baseline,
         mut report_progress: Box<dyn FnMut(usize, usize)>,
-    ) {
+    ) -> Result<usize, String> {
         let old_vals = mem::take(&mut self.values);
         let old_addrs = mem::take(&mut self.addresses);
-        let mut f_if_true = |val: T, addr: usize| {
-            self.values.push(val);
-            self.addresses.push(addr);
+        let base_vals = match baseline {
+            Baseline::Previous => &old_vals,
+            Baseline::First => self.earlier_values.first().unwrap_or(&old_vals),
+            Baseline::Pass(pass) if (1..self.passes).contains(pass) => {
+                &self.earlier_values[pass - 1]
+            }
+            Baseline::Pass(pass) if *pass == self.passes => &old_vals,
+            Baseline::Pass(pass) => {
+                let err = format!("there is no pass {}, passes done: {}", pass, self.passes);
+                self.values = old_vals;
+                self.addresses = old_addrs;
+                return Err(err);
+            }
+            Baseline::Snapshot(name) => match self.snapshots.iter().find(|(n, _)| n == name) {
+                Some((_, values)) => values,
+                None => {
+                    self.values = old_vals;
+                    self.addresses = old_addrs;
+                    return Err(format!("there is no snapshot named {:?}", name));
+                }
+            },
         };
         let to_scan = old_addrs.len();
-        let mut addrs_iter = old_addrs.into_iter().enumerate();
-        let f = iter::from_fn(|| {
-            if let Some((i, ret)) = addrs_iter.next() {
-                if i % 1000 == 0 {
-                    report_progress(i, to_scan);
+        let threads = ctx.config.threads;
+        let per_thread = to_scan.div_ceil(threads).max(1);
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let dropped = AtomicUsize::new(0);
+
+        let found = run_workers(threads, &scanned, to_scan, &mut report_progress, || {
+            let mut memory_reader = U::new(ctx.process.as_ref().unwrap());
+            let mut found = Vec::new();
+            loop {
+                let idx = next_part.fetch_add(1, Ordering::Relaxed);
+                let start = idx * per_thread;
+                if start >= to_scan {
+                    break found;
                 }
-                Some(ret)
-            } else {
-                None
+                let end = (start + per_thread).min(to_scan);
+                let mut values = Vec::new();
+                let mut addresses = Vec::new();
+                let mut f_if_true = |val: T, addr: usize| {
+                    values.push(val);
+                    addresses.push(addr);
+                };
+                for batch in (start..end).step_by(PROGRESS_BATCH) {
+                    let batch_end = (batch + PROGRESS_BATCH).min(end);
+                    let mut readable = Vec::with_capacity(batch_end - batch);
+                    let vals = (batch..batch_end).filter_map(|i| {
+                        // Addresses that can't be read anymore, e.g.
+                        // because their region got munmapped, are dropped
+                        let val = memory_reader.try_read::<T>(old_addrs[i]);
+                        readable.push(val.is_some());
+                        val.map(|val| (val, base_vals[i]))
+                    });
+                    let vals: Vec<_> = vals.collect();
+                    let addrs = (batch..batch_end)
+                        .zip(readable.iter())
+                        .filter(|(_, &readable)| readable)
+                        .map(|(i, _)| old_addrs[i]);
+                    dropped.fetch_add(batch_end - batch - vals.len(), Ordering::Relaxed);
+                    expr.eval_expr(&mut f_if_true, vals.into_iter(), addrs);
+                    scanned.fetch_add(batch_end - batch, Ordering::Relaxed);
+                }
+                found.push((idx, values, addresses));
             }
         });
-        expr.eval_expr(ctx, &mut f_if_true, old_vals.into_iter(), f);
+        self.merge(found);
+        self.keep_history(&old_addrs, old_vals);
         report_progress(to_scan, to_scan);
+        Ok(dropped.into_inner())
+    }
+
+    /// Add the values from the previous pass to the history and remove
+    /// values of addresses that didn't make it through the last scan
+    /// from it and from the snapshots
+    fn keep_history(&mut self, old_addrs: &[usize], old_vals: Vec<T>) {
+        // Both address lists are sorted and the new one is a subset
+        // of the old one
+        let mut kept = Vec::with_capacity(self.addresses.len());
+        let mut old = old_addrs.iter().enumerate();
+        for addr in &self.addresses {
+            if let Some((idx, _)) = old.find(|(_, old_addr)| *old_addr == addr) {
+                kept.push(idx);
+            }
+        }
+        self.earlier_values.push(old_vals);
+        let history = self
+            .earlier_values
+            .iter_mut()
+            .chain(self.snapshots.iter_mut().map(|(_, values)| values));
+        for values in history {
+            *values = kept.iter().map(|&idx| values[idx]).collect();
+        }
     }
 
     fn initial_scan(
@@ -258,39 +848,315 @@ where
         ctx: &Context,
         expr: &ScanExpr,
         mut report_progress: Box<dyn FnMut(usize, usize)>,
-    ) {
-        let memory_maps = &ctx.process.as_ref().unwrap().memory_maps;
-        let calc_addr_num =
-            |mm: &MemoryMap| (mm.addr_end - mm.addr_start) / mem::size_of::<usize>();
+    ) -> usize {
+        if let ScanExpr::Unknown = expr {
+            self.snapshot_regions(ctx, report_progress);
+            return 0;
+        }
+        let process = ctx.process.as_ref().unwrap();
+        let memory_maps = &process.memory_maps;
+        let size = mem::size_of::<T>();
+        let step = ctx.config.alignment.step(size);
+        let calc_addr_num = |mm: &MemoryMap| (mm.addr_end - mm.addr_start) / step;
         let to_scan: usize = memory_maps.iter().map(calc_addr_num).sum();
+        let unreadable: usize = memory_maps
+            .iter()
+            .filter(|mm| !mm.perms.read)
+            .map(calc_addr_num)
+            .sum();
 
-        let mut scanned = 0;
-        for memory_map in memory_maps.iter() {
-            scanned += calc_addr_num(memory_map);
+        // Big maps are split in parts, so a single huge heap doesn't
+        // end up being scanned by one thread while the rest idle
+        let parts: Vec<_> = memory_maps
+            .iter()
+            .filter(|mm| mm.perms.read)
+            .flat_map(|mm| {
+                (mm.addr_start..mm.addr_end)
+                    .step_by(PART_SIZE)
+                    .map(move |start| (start, (start + PART_SIZE).min(mm.addr_end), mm))
+            })
+            .collect();
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(unreadable);
+
+        let found = run_workers(ctx.config.threads, &scanned, to_scan, &mut report_progress, || {
+            let mut memory_reader = U::new(process);
+            let mut found = Vec::new();
+            loop {
+                let idx = next_part.fetch_add(1, Ordering::Relaxed);
+                let Some(&(start, end, memory_map)) = parts.get(idx) else {
+                    break found;
+                };
+                let region_start = memory_map.addr_start;
+                let mut values = Vec::new();
+                let mut addresses = Vec::new();
+                let mut f_if_true = |val: T, addr: usize| {
+                    values.push(val);
+                    addresses.push(addr);
+                };
+                memory_reader.read_region(
+                    start,
+                    end,
+                    memory_map.addr_end,
+                    size - 1,
+                    |chunk_addr, chunk, owned| {
+                        // Keep addresses aligned relative to the region start
+                        let first = (step - (chunk_addr - region_start) % step) % step;
+                        let offsets = (first..owned)
+                            .step_by(step)
+                            .take_while(|offset| offset + size <= chunk.len());
+                        let vals = offsets.clone().map(|offset| {
+                            let val = T::from_le_bytes(&chunk[offset..offset + size]);
+                            (val, val)
+                        });
+                        let addrs = offsets.map(|offset| chunk_addr + offset);
+                        expr.eval_expr(&mut f_if_true, vals, addrs);
+                        scanned.fetch_add(owned / step, Ordering::Relaxed);
+                    },
+                );
+                found.push((idx, values, addresses));
+            }
+        });
+        self.merge(found);
+        report_progress(to_scan, to_scan);
+        0
+    }
+
+    /// Copy all readable memory for an unknown value scan. Addresses
+    /// only get picked out of it once a filter narrows them down.
+    fn snapshot_regions(
+        &mut self,
+        ctx: &Context,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) {
+        let process = ctx.process.as_ref().unwrap();
+        let size = mem::size_of::<T>();
+        let step = ctx.config.alignment.step(size);
+        let parts: Vec<_> = process
+            .memory_maps
+            .iter()
+            .filter(|mm| mm.perms.read)
+            .flat_map(|mm| {
+                (mm.addr_start..mm.addr_end)
+                    .step_by(PART_SIZE)
+                    .map(move |start| (start, (start + PART_SIZE).min(mm.addr_end), mm))
+            })
+            .collect();
+        let to_scan = parts.iter().map(|(start, end, _)| end - start).sum();
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let found = run_workers(ctx.config.threads, &scanned, to_scan, &mut report_progress, || {
+            let mut memory_reader = U::new(process);
+            let mut found = Vec::new();
+            loop {
+                let idx = next_part.fetch_add(1, Ordering::Relaxed);
+                let Some(&(start, end, memory_map)) = parts.get(idx) else {
+                    break found;
+                };
+                memory_reader.read_region(start, end, end, 0, |chunk_addr, chunk, owned| {
+                    found.push(RegionSnapshot {
+                        addr: chunk_addr,
+                        bytes: chunk[..owned].to_vec(),
+                    });
+                });
+                scanned.fetch_add(end - start, Ordering::Relaxed);
+                // Regions don't span multiple maps
+                found.push(RegionSnapshot {
+                    addr: memory_map.addr_end,
+                    bytes: Vec::new(),
+                });
+            }
+        });
 
-            if !memory_map.perms.read {
+        // Glue the chunks back together, the empty ones mark the ends
+        // of maps
+        let mut chunks: Vec<_> = found.into_iter().flatten().collect();
+        chunks.sort_by_key(|chunk| (chunk.addr, !chunk.bytes.is_empty()));
+        let mut regions: Vec<RegionSnapshot> = Vec::new();
+        let mut map_ended = true;
+        for chunk in chunks {
+            if chunk.bytes.is_empty() {
+                map_ended = true;
                 continue;
             }
-            let step = std::mem::size_of::<T>();
-            let addrs = (memory_map.addr_start..memory_map.addr_end).step_by(step);
-            let mut addrs_cpy = addrs.clone();
-            let vals = iter::from_fn(|| match addrs_cpy.next() {
-                Some(addr) => Some(self.memory_reader.read::<T>(addr)),
-                None => None,
-            });
-            let mut f_if_true = |val: T, addr: usize| {
-                self.values.push(val);
-                self.addresses.push(addr);
-            };
-            expr.eval_expr(ctx, &mut f_if_true, vals, addrs);
-            report_progress(scanned, to_scan);
+            match regions.last_mut() {
+                Some(last) if !map_ended && last.addr + last.bytes.len() == chunk.addr => {
+                    last.bytes.extend_from_slice(&chunk.bytes);
+                }
+                _ => regions.push(chunk),
+            }
+            map_ended = false;
         }
+
+        self.region_len = regions
+            .iter()
+            .map(|region| Self::region_offsets(region, step, size).count())
+            .sum();
+        self.region_step = step;
+        self.regions = Arc::new(regions);
+        report_progress(to_scan, to_scan);
     }
+
+    /// Offsets of the values in region, aligned to step
+    fn region_offsets(
+        region: &RegionSnapshot,
+        step: usize,
+        size: usize,
+    ) -> impl Iterator<Item = usize> + Clone {
+        let first = (step - region.addr % step) % step;
+        let len = region.bytes.len();
+        (first..len)
+            .step_by(step)
+            .take_while(move |offset| offset + size <= len)
+    }
+
+    /// First filter after an unknown value scan. Compares the current
+    /// memory with the region snapshots and keeps what matches.
+    fn region_scan(
+        &mut self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        baseline: &Baseline,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> Result<usize, String> {
+        match baseline {
+            Baseline::Previous | Baseline::First | Baseline::Pass(1) => (),
+            Baseline::Pass(pass) => {
+                return Err(format!("there is no pass {}, passes done: 1", pass))
+            }
+            Baseline::Snapshot(name) => {
+                return Err(format!("there is no snapshot named {:?}", name))
+            }
+        }
+        let process = ctx.process.as_ref().unwrap();
+        let size = mem::size_of::<T>();
+        let step = self.region_step;
+        let regions = Arc::clone(&self.regions);
+        let parts: Vec<_> = regions
+            .iter()
+            .flat_map(|region| {
+                (0..region.bytes.len())
+                    .step_by(PART_SIZE)
+                    .map(move |start| (start, (start + PART_SIZE).min(region.bytes.len()), region))
+            })
+            .collect();
+        let to_scan = self.region_len;
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let found = run_workers(ctx.config.threads, &scanned, to_scan, &mut report_progress, || {
+            let mut memory_reader = U::new(process);
+            let mut found = Vec::new();
+            loop {
+                let idx = next_part.fetch_add(1, Ordering::Relaxed);
+                let Some(&(start, end, region)) = parts.get(idx) else {
+                    break found;
+                };
+                let mut values = Vec::new();
+                let mut addresses = Vec::new();
+                let mut f_if_true = |val: T, addr: usize| {
+                    values.push(val);
+                    addresses.push(addr);
+                };
+                let region_end = region.addr + region.bytes.len();
+                memory_reader.read_region(
+                    region.addr + start,
+                    region.addr + end,
+                    region_end,
+                    size - 1,
+                    |chunk_addr, chunk, owned| {
+                        let first = (step - chunk_addr % step) % step;
+                        let offsets = (first..owned)
+                            .step_by(step)
+                            .take_while(|offset| offset + size <= chunk.len());
+                        let vals = offsets.clone().map(|offset| {
+                            let old = chunk_addr - region.addr + offset;
+                            (
+                                T::from_le_bytes(&chunk[offset..offset + size]),
+                                T::from_le_bytes(&region.bytes[old..old + size]),
+                            )
+                        });
+                        let addrs = offsets.clone().map(|offset| chunk_addr + offset);
+                        expr.eval_expr(&mut f_if_true, vals, addrs);
+                        scanned.fetch_add(offsets.count(), Ordering::Relaxed);
+                    },
+                );
+                found.push((idx, values, addresses));
+            }
+        });
+        self.merge(found);
+
+        // The snapshot becomes the first pass
+        let first_pass = self
+            .addresses
+            .iter()
+            .map(|&addr| {
+                let idx = regions.partition_point(|region| region.addr <= addr) - 1;
+                let offset = addr - regions[idx].addr;
+                T::from_le_bytes(&regions[idx].bytes[offset..offset + size])
+            })
+            .collect();
+        self.earlier_values.push(first_pass);
+        self.regions = Arc::new(Vec::new());
+        self.region_len = 0;
+        report_progress(to_scan, to_scan);
+        // Whatever wasn't looked at became unreadable
+        Ok(to_scan - scanned.into_inner())
+    }
+
+    /// Put together results from workers. Every part of the work has
+    /// an index and parts with lower indices have lower addresses, so
+    /// sorting by it keeps the addresses sorted.
+    fn merge(&mut self, found: Vec<Vec<Found<T>>>) {
+        let mut found: Vec<_> = found.into_iter().flatten().collect();
+        found.sort_unstable_by_key(|(idx, _, _)| *idx);
+        let len = found.iter().map(|(_, values, _)| values.len()).sum();
+        self.values.reserve(len);
+        self.addresses.reserve(len);
+        for (_, values, addresses) in found {
+            self.values.extend(values);
+            self.addresses.extend(addresses);
+        }
+    }
+}
+
+/// Index of a part of the work, with values and addresses found in it
+type Found<T> = (usize, Vec<T>, Vec<usize>);
+
+/// Run worker on the given number of threads and return what each of
+/// them returned. Workers are expected to add their progress to
+/// scanned, which is reported from the calling thread until they all
+/// finish.
+pub(crate) fn run_workers<R, F>(
+    threads: usize,
+    scanned: &AtomicUsize,
+    to_scan: usize,
+    report_progress: &mut Box<dyn FnMut(usize, usize)>,
+    worker: F,
+) -> Vec<R>
+where
+    R: Send,
+    F: Fn() -> R + Sync,
+{
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads.max(1)).map(|_| scope.spawn(&worker)).collect();
+        while !handles.iter().all(|handle| handle.is_finished()) {
+            report_progress(scanned.load(Ordering::Relaxed).min(to_scan), to_scan);
+            thread::sleep(Duration::from_millis(50));
+        }
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
-    use crate::memory_reader::MemoryReaderSimple;
+    use crate::commands::Alignment;
+    use crate::memory_reader::{MemoryReaderSimple, MemoryReaderVm};
     use std::process;
 
     #[test]
@@ -298,18 +1164,353 @@ mod tests {
         let mut ctx = Context::new();
         ctx.process = Some(Process::try_new(process::id()).unwrap());
         let process = ctx.process.as_ref().unwrap();
-        let weird_numbers = vec![0xc0ffee, 0xc0ffee, 0xc0ffee];
-        let scan_expr = ScanExpr::Equal(weird_numbers[0].to_string());
+        let weird_numbers = [0xc0ffee, 0xc0ffee, 0xc0ffee];
+        let scan_expr = ScanExpr::Equal(weird_numbers[0].to_string(), FloatCmp::default());
         let mut addrs = AddrsSimple::<i32, MemoryReaderSimple>::new(process);
-        addrs.scan(&ctx, &scan_expr, Box::new(|_, _| ()));
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
 
         assert!(addrs.len() >= weird_numbers.len());
 
         let addr1 = (&weird_numbers[0] as *const i32) as usize;
         let addr2 = (&weird_numbers[1] as *const i32) as usize;
         let addr3 = (&weird_numbers[2] as *const i32) as usize;
-        assert!(addrs.get_addrs().contains(&addr1));
-        assert!(addrs.get_addrs().contains(&addr2));
-        assert!(addrs.get_addrs().contains(&addr3));
+        assert!(addrs.get_addrs().unwrap().contains(&addr1));
+        assert!(addrs.get_addrs().unwrap().contains(&addr2));
+        assert!(addrs.get_addrs().unwrap().contains(&addr3));
+    }
+
+    #[test]
+    fn scan_addrs_simple_threads() {
+        // Allocated first so its mapping is listed in memory maps
+        let values = vec![0x1337c0de_u32; 100_000];
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        ctx.config.threads = 4;
+        let process = ctx.process.as_ref().unwrap();
+        let scan_expr = ScanExpr::Equal(values[0].to_string(), FloatCmp::default());
+        let mut addrs = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+
+        let found = addrs.get_addrs().unwrap();
+        assert!(found.len() >= values.len());
+        assert!(found.windows(2).all(|w| w[0] < w[1]));
+        for value in values.iter().step_by(997) {
+            assert!(found.contains(&(value as *const u32 as usize)));
+        }
+
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        let refound = addrs.get_addrs().unwrap();
+        assert!(refound.windows(2).all(|w| w[0] < w[1]));
+        for value in values.iter() {
+            assert!(refound.binary_search(&(value as *const u32 as usize)).is_ok());
+        }
+    }
+
+    #[test]
+    fn scan_addrs_simple_unaligned() {
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let bytes = Box::new([0u8, 0xde, 0xc0, 0xad, 0x0b, 0]);
+        let addr = bytes.as_ptr() as usize + 1;
+        let scan_expr = ScanExpr::Equal(0x0badc0de.to_string(), FloatCmp::default());
+
+        let mut addrs = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(!addrs.get_addrs().unwrap().contains(&addr));
+
+        ctx.config.alignment = Alignment::One;
+        let mut addrs = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+    }
+
+    #[test]
+    fn scan_addrs_simple_drops_unreadable() {
+        let page = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                4096,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(page, libc::MAP_FAILED);
+        let addr = page as usize + 8;
+        unsafe { *(addr as *mut u32) = 0x5eed5eed };
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let scan_expr = ScanExpr::Equal(0x5eed5eed.to_string(), FloatCmp::default());
+        let mut addrs = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+
+        // Not munmap, another test's thread could map something there
+        unsafe { libc::mprotect(page, 4096, libc::PROT_NONE) };
+        let dropped = addrs
+            .scan(&ctx, &ScanExpr::NotChanged, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(dropped >= 1);
+        assert!(!addrs.get_addrs().unwrap().contains(&addr));
+        unsafe { libc::munmap(page, 4096) };
+    }
+
+    #[test]
+    fn scan_addrs_simple_changed() {
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let mut value = Box::new(0x7e57ab1e_u32);
+        let addr = value.as_ref() as *const u32 as usize;
+        let scan_expr = ScanExpr::Equal(value.to_string(), FloatCmp::default());
+        let mut addrs = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+
+        *value = std::hint::black_box(7);
+        addrs
+            .scan(&ctx, &ScanExpr::Changed, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        let idx = addrs.get_addrs().unwrap().iter().position(|&a| a == addr).unwrap();
+        assert_eq!(addrs.get_vals().unwrap()[idx], "7");
+        addrs
+            .scan(&ctx, &ScanExpr::NotChanged, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+    }
+
+    #[test]
+    fn scan_addrs_simple_unknown() {
+        let mut values = vec![0x0dd_u64; 10_000];
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        ctx.config.threads = 4;
+        let process = ctx.process.as_ref().unwrap();
+        let mut addrs = AddrsSimple::<u64, MemoryReaderVm>::new(process);
+        addrs
+            .scan(&ctx, &ScanExpr::Unknown, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.len() >= values.len());
+        assert!(addrs.get_addrs().is_err());
+        assert!(addrs.get_addr(0).is_err());
+        assert!(addrs.get_vals_to_print(10).is_err());
+        assert!(addrs.snapshot("too early").is_err());
+
+        values[1234] = std::hint::black_box(0x0dd + 5);
+        let addr = &values[1234] as *const u64 as usize;
+        let scan_expr = ScanExpr::IncreasedBy("5".to_string(), FloatCmp::Exact);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        let found = addrs.get_addrs().unwrap();
+        assert!(found.windows(2).all(|w| w[0] < w[1]));
+        let idx = found.binary_search(&addr).unwrap();
+        assert_eq!(addrs.get_vals().unwrap()[idx], (0x0dd + 5).to_string());
+        assert!(found.binary_search(&(&values[1233] as *const u64 as usize)).is_err());
+
+        // The snapshot is the first pass
+        values[1234] = std::hint::black_box(0x0dd);
+        addrs
+            .scan(&ctx, &ScanExpr::NotChanged, &Baseline::First, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+    }
+
+    #[test]
+    fn scan_addrs_simple_baselines() {
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let mut value = Box::new(0xba5e11_u32);
+        let addr = value.as_ref() as *const u32 as usize;
+        let scan_expr = ScanExpr::Equal(value.to_string(), FloatCmp::default());
+        let mut addrs = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        assert!(addrs.snapshot("early").is_err());
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::First, Box::new(|_, _| ()))
+            .unwrap();
+
+        *value = std::hint::black_box(0xba5e12);
+        addrs
+            .scan(&ctx, &ScanExpr::Increased, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        addrs.snapshot("middle").unwrap();
+        assert_eq!(addrs.snapshot_names(), vec!["middle".to_string()]);
+
+        *value = std::hint::black_box(0xba5e13);
+        addrs
+            .scan(&ctx, &ScanExpr::Increased, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+
+        // Back to the value of the first pass
+        *value = std::hint::black_box(0xba5e11);
+        addrs
+            .scan(&ctx, &ScanExpr::NotChanged, &Baseline::First, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+        let snapshot = Baseline::Snapshot("middle".to_string());
+        addrs
+            .scan(&ctx, &ScanExpr::Decreased, &snapshot, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+        addrs
+            .scan(&ctx, &ScanExpr::Decreased, &Baseline::Pass(3), Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+
+        let len = addrs.len();
+        let missing = Baseline::Snapshot("missing".to_string());
+        assert!(addrs.scan(&ctx, &ScanExpr::Changed, &missing, Box::new(|_, _| ())).is_err());
+        assert!(addrs
+            .scan(&ctx, &ScanExpr::Changed, &Baseline::Pass(99), Box::new(|_, _| ()))
+            .is_err());
+        assert_eq!(addrs.len(), len);
+    }
+
+    #[test]
+    fn scan_addrs_simple_vm_reader() {
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let value = Box::new(0xc0ffeeu64);
+        let scan_expr = ScanExpr::Equal(value.to_string(), FloatCmp::default());
+        let mut addrs = AddrsSimple::<u64, MemoryReaderVm>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+
+        let addr = (value.as_ref() as *const u64) as usize;
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+    }
+
+    #[test]
+    fn scan_addrs_float_rounding() {
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let health = Box::new(99.99998f32);
+        let scan_expr = ScanExpr::Equal("100.0".to_string(), FloatCmp::Rounded);
+        let mut addrs = AddrsSimple::<f32, MemoryReaderSimple>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+
+        let addr = (health.as_ref() as *const f32) as usize;
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+    }
+
+    #[test]
+    fn scan_expr_relative() {
+        let eval = |expr: ScanExpr, vals: Vec<(i16, i16)>| {
+            let mut found = Vec::new();
+            let addrs = 0..vals.len();
+            expr.eval_expr(&mut |_, addr| found.push(addr), vals.into_iter(), addrs);
+            found
+        };
+        let vals = vec![(5, 4), (4, 5), (4, 4), (-32768, 32767), (150, 100), (40, 100), (5, 0)];
+        assert_eq!(eval(ScanExpr::Increased, vals.clone()), vec![0, 4, 6]);
+        assert_eq!(eval(ScanExpr::Decreased, vals.clone()), vec![1, 3, 5]);
+        let cmp = FloatCmp::default();
+        assert_eq!(eval(ScanExpr::IncreasedBy("1".into(), cmp), vals.clone()), vec![0]);
+        assert_eq!(eval(ScanExpr::DecreasedBy("1".into(), cmp), vals.clone()), vec![1]);
+        assert_eq!(eval(ScanExpr::DecreasedBy("60".into(), cmp), vals.clone()), vec![5]);
+        assert_eq!(eval(ScanExpr::IncreasedByPercent("50".into()), vals.clone()), vec![4]);
+        assert_eq!(eval(ScanExpr::DecreasedByPercent("20".into()), vals.clone()), vec![1, 3, 5]);
+
+        let mut found = Vec::new();
+        let vals = vec![(9.5f32, 10.0), (9.0, 10.0)];
+        let expr = ScanExpr::DecreasedBy("0.5".into(), cmp);
+        expr.eval_expr(&mut |_, addr| found.push(addr), vals.into_iter(), 0..2);
+        assert_eq!(found, vec![0]);
+    }
+
+    #[test]
+    fn scan_expr_check_operands() {
+        let expr = |operand: &str| ScanExpr::Equal(operand.to_string(), FloatCmp::default());
+        assert!(expr("-3").check_operands(ValType::I32).is_ok());
+        assert!(expr("abc").check_operands(ValType::I32).is_err());
+        assert!(expr("1.5").check_operands(ValType::I32).is_err());
+        assert!(expr("1.5").check_operands(ValType::F32).is_ok());
+        let between = ScanExpr::Between("1".to_string(), "300".to_string(), Bounds::Inclusive);
+        assert!(between.check_operands(ValType::U8).is_err());
+        let percent = ScanExpr::IncreasedByPercent("12.5".to_string());
+        let not = ScanExpr::Not(Box::new(ScanExpr::And(Box::new(percent), Box::new(expr("x")))));
+        assert!(not.check_operands(ValType::U8).is_err());
+        assert!(expr("0F ??").check_operands(ValType::Aob).is_ok());
+    }
+
+    #[test]
+    fn scan_expr_between() {
+        let eval = |expr: ScanExpr| {
+            let mut found = Vec::new();
+            let vals = [1u8, 10, 15, 20, 21].map(|val| (val, val));
+            expr.eval_expr(&mut |val, _| found.push(val), vals.into_iter(), 0..5);
+            found
+        };
+        let (lower, upper) = ("10".to_string(), "20".to_string());
+        let between = |bounds| ScanExpr::Between(lower.clone(), upper.clone(), bounds);
+        let not_between = |bounds| ScanExpr::NotBetween(lower.clone(), upper.clone(), bounds);
+        assert_eq!(eval(between(Bounds::Inclusive)), vec![10, 15, 20]);
+        assert_eq!(eval(between(Bounds::Exclusive)), vec![15]);
+        assert_eq!(eval(not_between(Bounds::Inclusive)), vec![1, 21]);
+        assert_eq!(eval(not_between(Bounds::Exclusive)), vec![1, 10, 20, 21]);
+    }
+
+    #[test]
+    fn float_cmp_modes() {
+        let decimals = FloatCmp::decimals("100.0");
+        assert_eq!(decimals, 1);
+        assert_eq!(FloatCmp::decimals("1e-3"), 3);
+        assert_eq!(FloatCmp::decimals("1.5E-3"), 4);
+        assert_eq!(FloatCmp::decimals("1.25e1"), 1);
+        assert_eq!(FloatCmp::decimals("1e3"), 0);
+        assert!(99.96f64.approx_eq(100.0, FloatCmp::Rounded, decimals));
+        assert!(!99.94f64.approx_eq(100.0, FloatCmp::Rounded, decimals));
+        assert!(100.09f64.approx_eq(100.0, FloatCmp::Truncated, decimals));
+        assert!(!99.99f64.approx_eq(100.0, FloatCmp::Truncated, decimals));
+        assert!(99.01f64.approx_eq(100.0, FloatCmp::ExtremeRounded, 0));
+        assert!(!99.0f64.approx_eq(100.0, FloatCmp::ExtremeRounded, 0));
+        assert!(99.5f64.approx_eq(100.0, FloatCmp::Epsilon(0.5), decimals));
+        assert!(!99.99998f64.approx_eq(100.0, FloatCmp::Exact, decimals));
+        assert!(!5u8.approx_eq(6, FloatCmp::ExtremeRounded, 0));
+    }
+
+    #[test]
+    fn write_addrs_simple() {
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let bytes = Box::new([0x5au8, 0xa5, 0x5a, 0xa5]);
+        let addr = bytes.as_ptr() as usize;
+        let scan_expr = ScanExpr::Equal("165".to_string(), FloatCmp::default());
+        let mut addrs = AddrsSimple::<u8, MemoryReaderSimple>::new(process);
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        let idx = addrs.get_addrs().unwrap().iter().position(|&a| a == addr + 1).unwrap();
+
+        addrs.write("255", idx).unwrap();
+        assert!(addrs.write("256", idx).is_err());
+        assert!(addrs.write("1", addrs.len()).is_err());
+        let vals = addrs.get_vals_to_print(usize::MAX).unwrap();
+        assert_eq!(vals[idx], (addr + 1, "165".to_string(), "255".to_string()));
+        assert_eq!(MemoryReaderSimple::new(process).read::<u32>(addr), 0xa55aff5a);
     }
 }
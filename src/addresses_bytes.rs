@@ -0,0 +1,386 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::addresses::{run_workers, Addresses, Baseline, ScanExpr, PART_SIZE};
+use crate::commands::ValType;
+use crate::context::Context;
+use crate::memory_reader::MemoryReader;
+use crate::pattern::Pattern;
+use crate::process::Process;
+
+/// How many bytes of strings are read to print them, so the whole
+/// string shows up even when only its prefix was searched for
+const TEXT_PRINT_LEN: usize = 256;
+
+/// Index of a part of the work, with the addresses and bytes found in it
+type FoundBytes = (usize, Vec<usize>, Vec<u8>);
+
+/// Results of searching for byte patterns, which strings are turned
+/// into too. All values found by one pattern are as long as it is.
+///
+/// The first scan searches for `== pattern`, later ones can filter
+/// with `==` and `!=` a pattern of the same length, `changed` and
+/// `not-changed`. Only the previous pass can be used as a baseline.
+#[derive(Debug)]
+pub struct AddrsBytes<U: MemoryReader> {
+    val_type: ValType,
+    /// Length of every value
+    value_size: usize,
+    addresses: Vec<usize>,
+    /// Values of all addresses one after another
+    values: Vec<u8>,
+    /// Number of scans done so far
+    passes: usize,
+    memory_reader: U,
+}
+
+impl<U: MemoryReader + 'static> AddrsBytes<U> {
+    pub fn with_type(process: &Process, val_type: ValType) -> Self {
+        Self {
+            val_type,
+            value_size: 0,
+            addresses: Vec::new(),
+            values: Vec::new(),
+            passes: 0,
+            memory_reader: U::new(process),
+        }
+    }
+
+    fn value(&self, idx: usize) -> &[u8] {
+        &self.values[idx * self.value_size..(idx + 1) * self.value_size]
+    }
+
+    /// Current value at addr, or the whole string if we hold strings
+    fn read_current(&mut self, addr: usize) -> Option<Vec<u8>> {
+        if self.val_type.is_text() {
+            let mut current = vec![0; TEXT_PRINT_LEN.max(self.value_size)];
+            if self.memory_reader.read_bytes(addr, &mut current).is_ok() {
+                return Some(current);
+            }
+        }
+        let mut current = vec![0; self.value_size];
+        self.memory_reader.read_bytes(addr, &mut current).ok()?;
+        Some(current)
+    }
+
+    fn initial_scan(
+        &mut self,
+        ctx: &Context,
+        pattern: &Pattern,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) {
+        let process = ctx.process.as_ref().unwrap();
+        let len = pattern.len();
+        let step = ctx.config.alignment.step(1);
+        let parts: Vec<_> = process
+            .memory_maps
+            .iter()
+            .filter(|mm| mm.perms.read)
+            .flat_map(|mm| {
+                (mm.addr_start..mm.addr_end)
+                    .step_by(PART_SIZE)
+                    .map(move |start| (start, (start + PART_SIZE).min(mm.addr_end), mm.addr_end))
+            })
+            .collect();
+        let to_scan = parts.iter().map(|(start, end, _)| end - start).sum();
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let found = run_workers(ctx.config.threads, &scanned, to_scan, &mut report_progress, || {
+            let mut memory_reader = U::new(process);
+            let mut found = Vec::new();
+            loop {
+                let idx = next_part.fetch_add(1, Ordering::Relaxed);
+                let Some(&(start, end, limit)) = parts.get(idx) else {
+                    break found;
+                };
+                let mut addresses = Vec::new();
+                let mut values = Vec::new();
+                memory_reader.read_region(start, end, limit, len - 1, |chunk_addr, chunk, owned| {
+                    pattern.find_all(chunk, owned, |offset| {
+                        if (chunk_addr + offset) % step == 0 {
+                            addresses.push(chunk_addr + offset);
+                            values.extend_from_slice(&chunk[offset..offset + len]);
+                        }
+                    });
+                });
+                scanned.fetch_add(end - start, Ordering::Relaxed);
+                found.push((idx, addresses, values));
+            }
+        });
+
+        let mut found: Vec<FoundBytes> = found.into_iter().flatten().collect();
+        found.sort_unstable_by_key(|(idx, _, _)| *idx);
+        self.value_size = len;
+        for (_, addresses, values) in found {
+            self.addresses.extend(addresses);
+            self.values.extend(values);
+        }
+        report_progress(to_scan, to_scan);
+    }
+
+    fn noninitial_scan(
+        &mut self,
+        expr: &ScanExpr,
+        pattern: Option<&Pattern>,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> usize {
+        let old_addrs = std::mem::take(&mut self.addresses);
+        let old_vals = std::mem::take(&mut self.values);
+        let to_scan = old_addrs.len();
+        let mut dropped = 0;
+        let mut current = vec![0; self.value_size];
+        for (idx, (&addr, old)) in old_addrs
+            .iter()
+            .zip(old_vals.chunks_exact(self.value_size))
+            .enumerate()
+        {
+            if idx % 1024 == 0 {
+                report_progress(idx, to_scan);
+            }
+            // Addresses that can't be read anymore are dropped
+            if self.memory_reader.read_bytes(addr, &mut current).is_err() {
+                dropped += 1;
+                continue;
+            }
+            let keep = match (expr, pattern) {
+                (ScanExpr::Equal(..), Some(pattern)) => pattern.matches(&current),
+                (ScanExpr::NotEqual(..), Some(pattern)) => !pattern.matches(&current),
+                (ScanExpr::Changed, _) => current != old,
+                (ScanExpr::NotChanged, _) => current == old,
+                _ => unreachable!(),
+            };
+            if keep {
+                self.addresses.push(addr);
+                self.values.extend_from_slice(&current);
+            }
+        }
+        report_progress(to_scan, to_scan);
+        dropped
+    }
+}
+
+impl<U: MemoryReader + 'static> Addresses for AddrsBytes<U> {
+    fn new(process: &Process) -> Self {
+        Self::with_type(process, ValType::Aob)
+    }
+
+    fn get_type(&self) -> String {
+        self.val_type.name()
+    }
+
+    fn value_size(&self) -> usize {
+        self.value_size
+    }
+
+    fn passes(&self) -> usize {
+        self.passes
+    }
+
+    fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    fn scan(
+        &mut self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        baseline: &Baseline,
+        report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> Result<usize, String> {
+        if !baseline.is_previous(self.passes) {
+            return Err(format!(
+                "only the previous pass can be compared to for {} values",
+                self.val_type.name()
+            ));
+        }
+        let pattern = match expr {
+            ScanExpr::Equal(pattern, _) | ScanExpr::NotEqual(pattern, _) => {
+                Some(self.val_type.parse_pattern(pattern)?)
+            }
+            ScanExpr::Changed | ScanExpr::NotChanged if self.passes > 0 => None,
+            _ => {
+                return Err(format!(
+                    "{} values can only be searched for with == and filtered with ==, != \
+                     changed and not-changed",
+                    self.val_type.name()
+                ))
+            }
+        };
+
+        let dropped = if self.passes > 0 {
+            if let Some(pattern) = &pattern {
+                if pattern.len() != self.value_size {
+                    return Err(format!(
+                        "pattern has to be {} bytes long like the found values",
+                        self.value_size
+                    ));
+                }
+            }
+            self.noninitial_scan(expr, pattern.as_ref(), report_progress)
+        } else {
+            match (expr, &pattern) {
+                (ScanExpr::Equal(..), Some(pattern)) => {
+                    self.initial_scan(ctx, pattern, report_progress)
+                }
+                _ => return Err("the first scan has to search for a pattern with ==".to_string()),
+            }
+            0
+        };
+        self.passes += 1;
+        Ok(dropped)
+    }
+
+    fn snapshot(&mut self, _name: &str) -> Result<(), String> {
+        Err(format!("snapshots aren't supported for {} values", self.val_type.name()))
+    }
+
+    fn snapshot_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn get_addrs(&self) -> Result<Vec<usize>, String> {
+        Ok(self.addresses.clone())
+    }
+
+    fn get_addr(&self, idx: usize) -> Result<usize, String> {
+        self.addresses
+            .get(idx)
+            .copied()
+            .ok_or(format!("there is no address with index {}", idx))
+    }
+
+    fn try_clone_box(&self) -> Result<Box<dyn Addresses>, String> {
+        Ok(Box::new(Self {
+            val_type: self.val_type,
+            value_size: self.value_size,
+            addresses: self.addresses.clone(),
+            values: self.values.clone(),
+            passes: self.passes,
+            memory_reader: self.memory_reader.clone(),
+        }))
+    }
+
+    fn get_vals(&self) -> Result<Vec<String>, String> {
+        Ok((0..self.len())
+            .map(|idx| self.val_type.format_bytes(self.value(idx)))
+            .collect())
+    }
+
+    fn get_vals_to_print(&mut self, max: usize) -> Result<Vec<(usize, String, String)>, String> {
+        Ok((0..self.len().min(max))
+            .map(|idx| {
+                let addr = self.addresses[idx];
+                let current = match self.read_current(addr) {
+                    Some(current) => self.val_type.format_bytes(&current),
+                    None => "??".to_string(),
+                };
+                (addr, self.val_type.format_bytes(self.value(idx)), current)
+            })
+            .collect())
+    }
+
+    fn write(&mut self, value: &str, addr_idx: usize) -> Result<(), String> {
+        let addr = self.get_addr(addr_idx)?;
+        if self.val_type.is_text() {
+            return self.val_type.write(&mut self.memory_reader, addr, value);
+        }
+        let bytes = self.val_type.parse_bytes(value)?;
+        if bytes.len() != self.value_size {
+            return Err(format!("value has to be {} bytes long", self.value_size));
+        }
+        self.memory_reader
+            .write_bytes(addr, &bytes)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addresses::FloatCmp;
+    use crate::commands::FilterArgs;
+    use crate::memory_reader::MemoryReaderVm;
+    use crate::util::filter_args_to_scan_expr;
+    use std::process;
+
+    #[test]
+    fn addrs_bytes_pattern_scan() {
+        let mut code = Box::new([0x90u8, 0x48, 0x8b, 0x05, 0x12, 0x34, 0x56, 0x78, 0x89, 0xc3]);
+        let addr = code.as_ptr() as usize + 1;
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let mut addrs = AddrsBytes::<MemoryReaderVm>::new(process);
+        assert!(addrs
+            .scan(&ctx, &ScanExpr::Changed, &Baseline::Previous, Box::new(|_, _| ()))
+            .is_err());
+
+        let expr = ScanExpr::Equal("48 8B 05 ?? ?? ?? 7? 89".to_string(), FloatCmp::Exact);
+        addrs
+            .scan(&ctx, &expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert_eq!(addrs.value_size(), 8);
+        let idx = addrs.get_addrs().unwrap().iter().position(|&a| a == addr).unwrap();
+        assert_eq!(addrs.get_vals().unwrap()[idx], "48 8B 05 12 34 56 78 89");
+
+        code[4] = std::hint::black_box(0xff);
+        addrs
+            .scan(&ctx, &ScanExpr::Changed, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        let idx = addrs.get_addrs().unwrap().iter().position(|&a| a == addr).unwrap();
+        assert_eq!(addrs.get_vals_to_print(usize::MAX).unwrap()[idx].2, "48 8B 05 FF 34 56 78 89");
+
+        assert!(addrs.write("01 02", idx).is_err());
+        addrs.write("48 8b 05 00 00 00 00 89", idx).unwrap();
+        assert_eq!(std::hint::black_box(code[4]), 0);
+        let short = ScanExpr::Equal("48 8B".to_string(), FloatCmp::Exact);
+        assert!(addrs
+            .scan(&ctx, &short, &Baseline::Previous, Box::new(|_, _| ()))
+            .is_err());
+    }
+
+    #[test]
+    fn addrs_bytes_text_scan() {
+        let mut name: Box<[u16]> = "Memori Player\0".encode_utf16().collect();
+        let addr = name.as_ptr() as usize;
+        // Only the low nibble of these matches the folded letters
+        let lookalikes: Vec<Box<[u16]>> = ["-=]ORI P", "-%]ORI P"]
+            .iter()
+            .map(|text| text.encode_utf16().collect())
+            .collect();
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let mut addrs = AddrsBytes::<MemoryReaderVm>::with_type(process, ValType::Utf16);
+        let filter_args = FilterArgs {
+            expr: vec!["memori".to_string(), "p".to_string()],
+            epsilon: None,
+            rounding: None,
+            exclusive: false,
+            baseline: None,
+            ignore_case: true,
+            prefix: true,
+        };
+        let expr = filter_args_to_scan_expr(&filter_args, ValType::Utf16).unwrap();
+        addrs
+            .scan(&ctx, &expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        assert_eq!(addrs.value_size(), 16);
+        let found = addrs.get_addrs().unwrap();
+        for lookalike in &lookalikes {
+            assert!(!found.contains(&(lookalike.as_ptr() as usize)));
+        }
+        let idx = found.iter().position(|&a| a == addr).unwrap();
+        assert_eq!(addrs.get_vals().unwrap()[idx], "\"Memori P\"");
+        assert_eq!(addrs.get_vals_to_print(usize::MAX).unwrap()[idx].2, "\"Memori Player\"");
+
+        assert!(addrs.write("Memori Player 2", idx).is_err());
+        addrs.write("Memori", idx).unwrap();
+        name = std::hint::black_box(name);
+        assert_eq!(String::from_utf16_lossy(&name[..7]), "Memori\0");
+    }
+}
@@ -0,0 +1,606 @@
+use std::fmt::Debug;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{any, str::FromStr};
+
+use crate::addresses::{run_workers, Addresses, ApproxEq, Baseline, Delta, ScanExpr, PART_SIZE};
+use crate::context::Context;
+use crate::memory_reader::{FromLeBytes, MemoryReader, ToLeBytes};
+use crate::process::Process;
+
+/// Positions of the hits in a region, in whichever form is smaller
+#[derive(Debug, Clone)]
+enum Hits {
+    /// Offsets from the start of the region
+    Offsets(Vec<u32>),
+    /// Bit n is set when there's a hit at offset n * step
+    Bitmap(Vec<u64>),
+}
+
+impl Hits {
+    /// offsets have to be sorted, aligned to step and less than span
+    fn new(offsets: Vec<u32>, span: usize, step: usize) -> Hits {
+        let bitmap_len = (span / step).div_ceil(64);
+        if bitmap_len * mem::size_of::<u64>() >= offsets.len() * mem::size_of::<u32>() {
+            return Hits::Offsets(offsets);
+        }
+        let mut bitmap = vec![0u64; bitmap_len];
+        for offset in offsets {
+            let bit = offset as usize / step;
+            bitmap[bit / 64] |= 1 << (bit % 64);
+        }
+        Hits::Bitmap(bitmap)
+    }
+
+    fn offsets(&self, step: usize) -> Vec<u32> {
+        match self {
+            Hits::Offsets(offsets) => offsets.clone(),
+            Hits::Bitmap(bitmap) => {
+                let mut offsets = Vec::new();
+                for (idx, &word) in bitmap.iter().enumerate() {
+                    let mut word = word;
+                    while word != 0 {
+                        let bit = idx * 64 + word.trailing_zeros() as usize;
+                        offsets.push((bit * step) as u32);
+                        word &= word - 1;
+                    }
+                }
+                offsets
+            }
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        match self {
+            Hits::Offsets(offsets) => offsets.len() * mem::size_of::<u32>(),
+            Hits::Bitmap(bitmap) => bitmap.len() * mem::size_of::<u64>(),
+        }
+    }
+}
+
+/// Hits in one part of a memory map. Parts are never bigger than
+/// `PART_SIZE`, so offsets from their start fit in a u32.
+#[derive(Debug, Clone)]
+struct Region<T> {
+    start: usize,
+    span: usize,
+    hits: Hits,
+    values: Vec<T>,
+}
+
+/// Index of a part of the work, with the region found in it
+type FoundRegion<T> = (usize, Region<T>);
+
+/// Stores hits per region as u32 offsets or as bitmaps, whichever is
+/// smaller, instead of a usize per hit like `AddrsSimple`. Dense
+/// results, like the ones of an unknown value scan, end up taking
+/// little more than the values themselves.
+///
+/// Only the previous pass can be used as a baseline.
+#[derive(Debug)]
+pub struct AddrsCompact<T, U>
+where
+    U: MemoryReader,
+{
+    regions: Vec<Region<T>>,
+    /// Number of scans done so far
+    passes: usize,
+    /// Distance between addresses, set by the initial scan
+    step: usize,
+    memory_reader: U,
+}
+
+impl<T, U> AddrsCompact<T, U>
+where
+    T: FromLeBytes
+        + ToLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + ToString
+        + Send
+        + Sync
+        + 'static,
+    T::Err: Debug,
+    U: MemoryReader + 'static,
+    [(); mem::size_of::<T>()]:,
+{
+    /// Bytes used for the hits and values
+    pub fn heap_size(&self) -> usize {
+        self.regions
+            .iter()
+            .map(|region| region.hits.heap_size() + region.values.len() * mem::size_of::<T>())
+            .sum()
+    }
+
+    /// Every found address with its value when scanned
+    fn hits(&self) -> impl Iterator<Item = (usize, T)> + '_ {
+        self.regions.iter().flat_map(|region| {
+            region
+                .hits
+                .offsets(self.step)
+                .into_iter()
+                .zip(region.values.iter().copied())
+                .map(|(offset, val)| (region.start + offset as usize, val))
+        })
+    }
+
+    fn initial_scan(
+        &mut self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) {
+        let process = ctx.process.as_ref().unwrap();
+        let size = mem::size_of::<T>();
+        let step = ctx.config.alignment.step(size);
+        let parts: Vec<_> = process
+            .memory_maps
+            .iter()
+            .filter(|mm| mm.perms.read)
+            .flat_map(|mm| {
+                (mm.addr_start..mm.addr_end)
+                    .step_by(PART_SIZE)
+                    .map(move |start| (start, (start + PART_SIZE).min(mm.addr_end), mm.addr_end))
+            })
+            .collect();
+        let to_scan = parts.iter().map(|(start, end, _)| end - start).sum();
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let found = run_workers(
+            ctx.config.threads,
+            &scanned,
+            to_scan,
+            &mut report_progress,
+            || {
+                let mut memory_reader = U::new(process);
+                let mut found = Vec::new();
+                loop {
+                    let idx = next_part.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(start, end, limit)) = parts.get(idx) else {
+                        break found;
+                    };
+                    let mut values = Vec::new();
+                    let mut offsets = Vec::new();
+                    let mut f_if_true = |val: T, addr: usize| {
+                        values.push(val);
+                        offsets.push((addr - start) as u32);
+                    };
+                    memory_reader.read_region(
+                        start,
+                        end,
+                        limit,
+                        size - 1,
+                        |chunk_addr, chunk, owned| {
+                            let first = (step - chunk_addr % step) % step;
+                            let chunk_offsets = (first..owned)
+                                .step_by(step)
+                                .take_while(|offset| offset + size <= chunk.len());
+                            let vals = chunk_offsets.clone().map(|offset| {
+                                let val = T::from_le_bytes(&chunk[offset..offset + size]);
+                                (val, val)
+                            });
+                            let addrs = chunk_offsets.map(|offset| chunk_addr + offset);
+                            expr.eval_expr(&mut f_if_true, vals, addrs);
+                        },
+                    );
+                    scanned.fetch_add(end - start, Ordering::Relaxed);
+                    if !values.is_empty() {
+                        let region = Region {
+                            start,
+                            span: end - start,
+                            hits: Hits::new(offsets, end - start, step),
+                            values,
+                        };
+                        found.push((idx, region));
+                    }
+                }
+            },
+        );
+        self.step = step;
+        self.regions = Self::merge(found);
+        report_progress(to_scan, to_scan);
+    }
+
+    fn noninitial_scan(
+        &mut self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> usize {
+        let process = ctx.process.as_ref().unwrap();
+        let size = mem::size_of::<T>();
+        let step = self.step;
+        let to_scan = self.len();
+        let next_region = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+        let dropped = AtomicUsize::new(0);
+
+        let found = run_workers(
+            ctx.config.threads,
+            &scanned,
+            to_scan,
+            &mut report_progress,
+            || {
+                let mut memory_reader = U::new(process);
+                let mut found = Vec::new();
+                loop {
+                    let idx = next_region.fetch_add(1, Ordering::Relaxed);
+                    let Some(region) = self.regions.get(idx) else {
+                        break found;
+                    };
+                    let mut addrs = Vec::with_capacity(region.values.len());
+                    let mut vals = Vec::with_capacity(region.values.len());
+                    let offsets = region.hits.offsets(step);
+                    // Addresses that can't be read anymore are dropped
+                    match region.hits {
+                        Hits::Offsets(_) => {
+                            for (offset, &old) in offsets.iter().zip(region.values.iter()) {
+                                let addr = region.start + *offset as usize;
+                                if let Some(val) = memory_reader.try_read::<T>(addr) {
+                                    addrs.push(addr);
+                                    vals.push((val, old));
+                                }
+                            }
+                        }
+                        // Dense, so the whole region is read at once
+                        Hits::Bitmap(_) => {
+                            let end = region.start + region.span;
+                            let mut hits = offsets.iter().zip(region.values.iter()).peekable();
+                            memory_reader.read_region(
+                                region.start,
+                                end,
+                                end + size - 1,
+                                size - 1,
+                                |chunk_addr, chunk, owned| {
+                                    let chunk_start = chunk_addr - region.start;
+                                    while let Some((&offset, &old)) =
+                                        hits.next_if(|(&offset, _)| {
+                                            (offset as usize) < chunk_start + owned
+                                        })
+                                    {
+                                        // Hits before the chunk were in unreadable pages
+                                        let Some(chunk_offset) =
+                                            (offset as usize).checked_sub(chunk_start)
+                                        else {
+                                            continue;
+                                        };
+                                        if chunk_offset + size <= chunk.len() {
+                                            let bytes = &chunk[chunk_offset..chunk_offset + size];
+                                            addrs.push(chunk_addr + chunk_offset);
+                                            vals.push((T::from_le_bytes(bytes), old));
+                                        }
+                                    }
+                                },
+                            );
+                        }
+                    }
+                    dropped.fetch_add(region.values.len() - vals.len(), Ordering::Relaxed);
+
+                    let mut values = Vec::new();
+                    let mut offsets = Vec::new();
+                    let mut f_if_true = |val: T, addr: usize| {
+                        values.push(val);
+                        offsets.push((addr - region.start) as u32);
+                    };
+                    expr.eval_expr(&mut f_if_true, vals.into_iter(), addrs.into_iter());
+                    scanned.fetch_add(region.values.len(), Ordering::Relaxed);
+                    if !values.is_empty() {
+                        let new_region = Region {
+                            start: region.start,
+                            span: region.span,
+                            hits: Hits::new(offsets, region.span, step),
+                            values,
+                        };
+                        found.push((idx, new_region));
+                    }
+                }
+            },
+        );
+        self.regions = Self::merge(found);
+        report_progress(to_scan, to_scan);
+        dropped.into_inner()
+    }
+
+    /// Put together regions from workers, sorted by the index of the
+    /// part of the work they came from, which sorts them by address
+    fn merge(found: Vec<Vec<FoundRegion<T>>>) -> Vec<Region<T>> {
+        let mut found: Vec<_> = found.into_iter().flatten().collect();
+        found.sort_unstable_by_key(|(idx, _)| *idx);
+        found.into_iter().map(|(_, region)| region).collect()
+    }
+}
+
+impl<T, U> Addresses for AddrsCompact<T, U>
+where
+    T: FromLeBytes
+        + ToLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + ToString
+        + Send
+        + Sync
+        + 'static,
+    T::Err: Debug,
+    U: MemoryReader + 'static,
+    [(); mem::size_of::<T>()]:,
+{
+    fn new(process: &Process) -> Self {
+        Self {
+            regions: Vec::new(),
+            passes: 0,
+            step: 1,
+            memory_reader: U::new(process),
+        }
+    }
+
+    fn get_type(&self) -> String {
+        any::type_name::<T>().to_string()
+    }
+
+    fn value_size(&self) -> usize {
+        mem::size_of::<T>()
+    }
+
+    fn passes(&self) -> usize {
+        self.passes
+    }
+
+    fn len(&self) -> usize {
+        self.regions.iter().map(|region| region.values.len()).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    fn scan(
+        &mut self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        baseline: &Baseline,
+        report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> Result<usize, String> {
+        if !baseline.is_previous(self.passes) {
+            return Err(
+                "only the previous pass can be compared to with compact storage".to_string(),
+            );
+        }
+        let dropped = if self.passes > 0 {
+            self.noninitial_scan(ctx, expr, report_progress)
+        } else {
+            self.initial_scan(ctx, expr, report_progress);
+            0
+        };
+        self.passes += 1;
+        Ok(dropped)
+    }
+
+    fn snapshot(&mut self, _name: &str) -> Result<(), String> {
+        Err("snapshots aren't supported with compact storage".to_string())
+    }
+
+    fn snapshot_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn get_addrs(&self) -> Result<Vec<usize>, String> {
+        Ok(self.hits().map(|(addr, _)| addr).collect())
+    }
+
+    fn get_addr(&self, idx: usize) -> Result<usize, String> {
+        self.hits()
+            .nth(idx)
+            .map(|(addr, _)| addr)
+            .ok_or(format!("there is no address with index {}", idx))
+    }
+
+    fn try_clone_box(&self) -> Result<Box<dyn Addresses>, String> {
+        Ok(Box::new(Self {
+            regions: self.regions.clone(),
+            passes: self.passes,
+            step: self.step,
+            memory_reader: self.memory_reader.clone(),
+        }))
+    }
+
+    fn get_vals(&self) -> Result<Vec<String>, String> {
+        Ok(self.hits().map(|(_, val)| val.to_string()).collect())
+    }
+
+    fn get_vals_to_print(&mut self, max: usize) -> Result<Vec<(usize, String, String)>, String> {
+        let hits: Vec<_> = self.hits().take(max).collect();
+        Ok(hits
+            .into_iter()
+            .map(|(addr, val)| {
+                let current = match self.memory_reader.try_read::<T>(addr) {
+                    Some(current) => current.to_string(),
+                    None => "??".to_string(),
+                };
+                (addr, val.to_string(), current)
+            })
+            .collect())
+    }
+
+    fn write(&mut self, value: &str, addr_idx: usize) -> Result<(), String> {
+        let addr = self.get_addr(addr_idx)?;
+        let value = value
+            .parse::<T>()
+            .map_err(|e| format!("couldn't parse {:?}: {:?}", value, e))?;
+        self.memory_reader
+            .write(addr, value)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addresses::{AddrsSimple, FloatCmp};
+    use crate::memory_reader::MemoryReaderVm;
+    use std::process;
+    use std::time::Instant;
+
+    #[test]
+    fn hits_bitmap_round_trip() {
+        let offsets = vec![0, 4, 8, 12, 16, 40];
+        let hits = Hits::new(offsets.clone(), 64, 4);
+        assert!(matches!(hits, Hits::Bitmap(_)));
+        assert_eq!(hits.offsets(4), offsets);
+
+        let sparse = Hits::new(vec![8, 4000], 1 << 20, 4);
+        assert!(matches!(sparse, Hits::Offsets(_)));
+        assert_eq!(sparse.offsets(4), vec![8, 4000]);
+    }
+
+    #[test]
+    fn addrs_compact_matches_simple() {
+        let mut values = vec![0xc0_u32; 100_000];
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        ctx.config.threads = 4;
+        let process = ctx.process.as_ref().unwrap();
+        let expr = ScanExpr::Equal(values[0].to_string(), FloatCmp::default());
+
+        let mut compact = AddrsCompact::<u32, MemoryReaderVm>::new(process);
+        compact
+            .scan(&ctx, &expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        let mut simple = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        simple
+            .scan(&ctx, &expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+
+        // Other tests change memory in between, so only compare what
+        // both found in the buffer
+        let buffer = values.as_ptr_range();
+        let in_buffer = |addrs: Vec<usize>| -> Vec<usize> {
+            let range = buffer.start as usize..buffer.end as usize;
+            addrs
+                .into_iter()
+                .filter(|addr| range.contains(addr))
+                .collect()
+        };
+        let found = in_buffer(compact.get_addrs().unwrap());
+        assert_eq!(found.len(), values.len());
+        assert_eq!(found, in_buffer(simple.get_addrs().unwrap()));
+
+        let simple_size = simple.len() * (mem::size_of::<usize>() + mem::size_of::<u32>());
+        assert!(compact.heap_size() < simple_size);
+
+        values[99] = std::hint::black_box(0xc1);
+        let addr = &values[99] as *const u32 as usize;
+        compact
+            .scan(
+                &ctx,
+                &ScanExpr::Changed,
+                &Baseline::Previous,
+                Box::new(|_, _| ()),
+            )
+            .unwrap();
+        let idx = compact
+            .get_addrs()
+            .unwrap()
+            .iter()
+            .position(|&a| a == addr)
+            .unwrap();
+        assert_eq!(compact.get_vals().unwrap()[idx], 0xc1.to_string());
+        compact.write("5", idx).unwrap();
+        assert_eq!(std::hint::black_box(values[99]), 5);
+    }
+
+    #[test]
+    fn addrs_compact_unknown_size() {
+        let mut values = vec![0u8; 1 << 20];
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let mut compact = AddrsCompact::<u8, MemoryReaderVm>::new(process);
+        compact
+            .scan(
+                &ctx,
+                &ScanExpr::Unknown,
+                &Baseline::Previous,
+                Box::new(|_, _| ()),
+            )
+            .unwrap();
+        assert!(compact.len() >= values.len());
+
+        // About a bit per address on top of the values, instead of
+        // a usize
+        assert!(compact.heap_size() < compact.len() * 2);
+
+        // Dense regions are read whole when filtering
+        values[12345] = std::hint::black_box(1);
+        compact
+            .scan(
+                &ctx,
+                &ScanExpr::Changed,
+                &Baseline::Previous,
+                Box::new(|_, _| ()),
+            )
+            .unwrap();
+        let addr = values.as_ptr() as usize + 12345;
+        let idx = compact
+            .get_addrs()
+            .unwrap()
+            .iter()
+            .position(|&a| a == addr)
+            .unwrap();
+        assert_eq!(compact.get_vals().unwrap()[idx], "1");
+    }
+
+    /// Time and memory of both stores for an unknown scan followed by
+    /// a filter. Run with
+    /// `cargo test --release bench_compact_vs_simple -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_compact_vs_simple() {
+        let mut values = vec![0u32; 16 << 20];
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let mut compact = AddrsCompact::<u32, MemoryReaderVm>::new(process);
+        let mut simple = AddrsSimple::<u32, MemoryReaderVm>::new(process);
+        let scan = |addrs: &mut dyn Addresses, expr: &ScanExpr| {
+            let start = Instant::now();
+            addrs
+                .scan(&ctx, expr, &Baseline::Previous, Box::new(|_, _| ()))
+                .unwrap();
+            start.elapsed()
+        };
+        let record_size = mem::size_of::<usize>() + mem::size_of::<u32>();
+
+        let compact_unknown = scan(&mut compact, &ScanExpr::Unknown);
+        let simple_unknown = scan(&mut simple, &ScanExpr::Unknown);
+        for value in values.iter_mut().step_by(3) {
+            *value = std::hint::black_box(1);
+        }
+        let compact_changed = scan(&mut compact, &ScanExpr::Changed);
+        let compact_size = compact.heap_size();
+        let simple_changed = scan(&mut simple, &ScanExpr::Changed);
+        let simple_size = simple.len() * record_size;
+
+        println!("{} hits", compact.len());
+        println!(
+            "compact: unknown {:?}, changed {:?}, {} MiB",
+            compact_unknown,
+            compact_changed,
+            compact_size >> 20
+        );
+        println!(
+            "simple:  unknown {:?}, changed {:?}, {} MiB",
+            simple_unknown,
+            simple_changed,
+            simple_size >> 20
+        );
+    }
+}
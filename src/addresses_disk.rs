@@ -0,0 +1,985 @@
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{any, str::FromStr};
+
+use crate::addresses::{
+    run_workers, Addresses, ApproxEq, Baseline, Delta, ScanExpr, PART_SIZE, PROGRESS_BATCH,
+    UNFILTERED_ERROR,
+};
+use crate::context::Context;
+use crate::memory_reader::{FromLeBytes, MemoryReader, ToLeBytes};
+use crate::process::Process;
+
+/// Size of the buffers between the files and the scanner
+const IO_BUFFER_SIZE: usize = 1024 * 1024;
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A file in the spill directory, deleted when dropped
+#[derive(Debug)]
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create(dir: &Path) -> io::Result<(TempFile, File)> {
+        let name = format!(
+            "memori-{}-{}",
+            process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok((TempFile { path }, file))
+    }
+
+    fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Part of the results. Segments are kept sorted by address.
+#[derive(Debug)]
+enum Segment {
+    /// Found addresses, each followed by its value from the last
+    /// pass, its values from the passes before it and its values in
+    /// the snapshots
+    Records { file: TempFile, len: usize },
+    /// Raw memory taken by an initial scan for unknown values. The
+    /// values start in the first `owned` bytes, the rest of the file
+    /// is there so the ones at the end can be read whole.
+    Region {
+        file: TempFile,
+        addr: usize,
+        owned: usize,
+        len: usize,
+    },
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        match self {
+            Segment::Records { len, .. } | Segment::Region { len, .. } => *len,
+        }
+    }
+}
+
+/// Writes found addresses and their values into a new segment
+struct RecordWriter {
+    file: TempFile,
+    out: BufWriter<File>,
+    len: usize,
+    /// The first error, writing stops after it
+    error: Option<io::Error>,
+}
+
+impl RecordWriter {
+    fn new(dir: &Path) -> io::Result<RecordWriter> {
+        let (file, out) = TempFile::create(dir)?;
+        Ok(RecordWriter {
+            file,
+            out: BufWriter::with_capacity(IO_BUFFER_SIZE, out),
+            len: 0,
+            error: None,
+        })
+    }
+
+    /// Write a record, the columns are the values kept as baselines
+    fn push<T: ToLeBytes>(&mut self, addr: usize, val: T, columns: &[&[u8]]) {
+        if self.error.is_some() {
+            return;
+        }
+        let written = self
+            .out
+            .write_all(&(addr as u64).to_le_bytes())
+            .and_then(|()| self.out.write_all(&val.to_le_bytes()))
+            .and_then(|()| {
+                columns
+                    .iter()
+                    .try_for_each(|column| self.out.write_all(column))
+            });
+        match written {
+            Ok(()) => self.len += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Empty segments aren't worth keeping a file for
+    fn finish(mut self) -> io::Result<Option<Segment>> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok((self.len > 0).then_some(Segment::Records {
+            file: self.file,
+            len: self.len,
+        }))
+    }
+}
+
+/// Writes memory copied by an initial scan for unknown values into a
+/// new segment
+struct RegionWriter {
+    file: TempFile,
+    out: BufWriter<File>,
+    addr: usize,
+    owned: usize,
+}
+
+impl RegionWriter {
+    fn new(dir: &Path, addr: usize) -> io::Result<RegionWriter> {
+        let (file, out) = TempFile::create(dir)?;
+        Ok(RegionWriter {
+            file,
+            out: BufWriter::with_capacity(IO_BUFFER_SIZE, out),
+            addr,
+            owned: 0,
+        })
+    }
+
+    /// tail are the bytes right after the region
+    fn finish(mut self, tail: &[u8], step: usize, size: usize) -> io::Result<Segment> {
+        self.out.write_all(tail)?;
+        self.out.flush()?;
+        let total = self.owned + tail.len();
+        Ok(Segment::Region {
+            len: region_offsets(self.addr, self.owned, total, step, size).count(),
+            file: self.file,
+            addr: self.addr,
+            owned: self.owned,
+        })
+    }
+}
+
+/// Read count records of record_size bytes from a segment into buffer
+fn read_records(
+    input: &mut impl Read,
+    count: usize,
+    record_size: usize,
+    buffer: &mut Vec<u8>,
+) -> io::Result<()> {
+    buffer.resize(count * record_size, 0);
+    input.read_exact(buffer)
+}
+
+/// Address and value from the last pass at the start of a record
+fn parse_record<T: FromLeBytes>(record: &[u8]) -> (usize, T) {
+    let (addr, columns) = record.split_at(mem::size_of::<u64>());
+    (
+        u64::from_le_bytes(addr.try_into().unwrap()) as usize,
+        T::from_le_bytes(&columns[..mem::size_of::<T>()]),
+    )
+}
+
+/// Offsets of the values in the region segment's memory, aligned to
+/// step
+fn region_offsets(
+    addr: usize,
+    owned: usize,
+    total: usize,
+    step: usize,
+    size: usize,
+) -> impl Iterator<Item = usize> + Clone {
+    let first = (step - addr % step) % step;
+    (first..owned)
+        .step_by(step)
+        .take_while(move |offset| offset + size <= total)
+}
+
+/// Index of a part of the work, with segments written for it
+type FoundSegments = (usize, Vec<Segment>);
+
+/// Keeps the results in temporary files instead of memory, for scans
+/// that would find more than fits in RAM. Scans stream the files
+/// and only need a few buffers per thread.
+///
+/// Values of earlier passes and snapshots are kept next to the
+/// current ones in the records, like `AddrsSimple` keeps them
+/// parallel to its addresses.
+#[derive(Debug)]
+pub struct AddrsDisk<T, U>
+where
+    U: MemoryReader,
+{
+    /// Files aren't changed once written, so copies of the result set
+    /// kept for undo share them instead of copying them
+    segments: Vec<Arc<Segment>>,
+    /// Number of scans done so far
+    passes: usize,
+    /// Names of the snapshots, in the order of their columns
+    snapshots: Vec<String>,
+    /// Where new files are created, set from the config by every scan
+    dir: PathBuf,
+    /// Distance between addresses in region segments
+    region_step: usize,
+    memory_reader: U,
+    _type: std::marker::PhantomData<T>,
+}
+
+impl<T, U> AddrsDisk<T, U>
+where
+    T: FromLeBytes
+        + ToLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + ToString
+        + Send
+        + Sync
+        + 'static,
+    T::Err: Debug,
+    U: MemoryReader + 'static,
+    [(); mem::size_of::<T>()]:,
+{
+    /// Size of a record: the address and a value per pass and snapshot
+    fn record_size(&self) -> usize {
+        mem::size_of::<u64>() + mem::size_of::<T>() * (self.passes + self.snapshots.len())
+    }
+
+    /// The first max records, in order. Region segments don't have
+    /// any, so reading fails while there are some.
+    fn read_first(&self, max: usize) -> Result<(Vec<usize>, Vec<T>), String> {
+        let mut addrs = Vec::new();
+        let mut vals = Vec::new();
+        let mut buffer = Vec::new();
+        for segment in &self.segments {
+            let remaining = max - addrs.len();
+            if remaining == 0 {
+                break;
+            }
+            let Segment::Records { file, len } = &**segment else {
+                return Err(UNFILTERED_ERROR.to_string());
+            };
+            file.open()
+                .and_then(|file| {
+                    let mut input = BufReader::with_capacity(IO_BUFFER_SIZE, file);
+                    let count = remaining.min(*len);
+                    read_records(&mut input, count, self.record_size(), &mut buffer)
+                })
+                .map_err(|e| format!("couldn't read results from disk: {}", e))?;
+            for record in buffer.chunks_exact(self.record_size()) {
+                let (addr, val) = parse_record(record);
+                addrs.push(addr);
+                vals.push(val);
+            }
+        }
+        Ok((addrs, vals))
+    }
+
+    /// Seek to the record with the given index instead of reading the
+    /// ones before it
+    fn read_record(&self, mut idx: usize) -> Result<(usize, T), String> {
+        let record_size = self.record_size();
+        for segment in &self.segments {
+            let Segment::Records { file, len } = &**segment else {
+                return Err(UNFILTERED_ERROR.to_string());
+            };
+            if idx >= *len {
+                idx -= len;
+                continue;
+            }
+            let mut record = Vec::new();
+            file.open()
+                .and_then(|mut input| {
+                    input.seek(SeekFrom::Start((idx * record_size) as u64))?;
+                    read_records(&mut input, 1, record_size, &mut record)
+                })
+                .map_err(|e| format!("couldn't read results from disk: {}", e))?;
+            return Ok(parse_record(&record));
+        }
+        Err(format!("there is no address with index {}", idx))
+    }
+
+    fn initial_scan(
+        &self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        report_progress: &mut Box<dyn FnMut(usize, usize)>,
+    ) -> io::Result<Vec<Segment>> {
+        let process = ctx.process.as_ref().unwrap();
+        let size = mem::size_of::<T>();
+        let step = ctx.config.alignment.step(size);
+        let unknown = matches!(expr, ScanExpr::Unknown);
+        let parts: Vec<_> = process
+            .memory_maps
+            .iter()
+            .filter(|mm| mm.perms.read)
+            .flat_map(|mm| {
+                (mm.addr_start..mm.addr_end)
+                    .step_by(PART_SIZE)
+                    .map(move |start| (start, (start + PART_SIZE).min(mm.addr_end), mm.addr_end))
+            })
+            .collect();
+        let to_scan = parts.iter().map(|(start, end, _)| end - start).sum();
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let found = run_workers(
+            ctx.config.threads,
+            &scanned,
+            to_scan,
+            report_progress,
+            || {
+                let mut memory_reader = U::new(process);
+                let mut found = Vec::new();
+                loop {
+                    let idx = next_part.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(start, end, limit)) = parts.get(idx) else {
+                        break Ok::<_, io::Error>(found);
+                    };
+                    let segments = if unknown {
+                        self.snapshot_part(&mut memory_reader, start, end, limit, step)?
+                    } else {
+                        let mut writer = RecordWriter::new(&self.dir)?;
+                        let mut f_if_true = |val: T, addr: usize| writer.push(addr, val, &[]);
+                        memory_reader.read_region(
+                            start,
+                            end,
+                            limit,
+                            size - 1,
+                            |chunk_addr, chunk, owned| {
+                                let offsets =
+                                    region_offsets(chunk_addr, owned, chunk.len(), step, size);
+                                let vals = offsets.clone().map(|offset| {
+                                    let val = T::from_le_bytes(&chunk[offset..offset + size]);
+                                    (val, val)
+                                });
+                                let addrs = offsets.map(|offset| chunk_addr + offset);
+                                expr.eval_expr(&mut f_if_true, vals, addrs);
+                            },
+                        );
+                        writer.finish()?.into_iter().collect()
+                    };
+                    scanned.fetch_add(end - start, Ordering::Relaxed);
+                    found.push((idx, segments));
+                }
+            },
+        );
+        report_progress(to_scan, to_scan);
+        Self::merge(found)
+    }
+
+    /// Copy memory in [start, end) to region segments, starting a new
+    /// one after every unreadable hole
+    fn snapshot_part(
+        &self,
+        memory_reader: &mut U,
+        start: usize,
+        end: usize,
+        limit: usize,
+        step: usize,
+    ) -> io::Result<Vec<Segment>> {
+        let size = mem::size_of::<T>();
+        let mut segments = Vec::new();
+        let mut current: Option<RegionWriter> = None;
+        // Bytes after the last chunk, needed for values at its end
+        let mut tail = Vec::new();
+        let mut result: io::Result<()> = Ok(());
+        memory_reader.read_region(start, end, limit, size - 1, |chunk_addr, chunk, owned| {
+            if result.is_err() {
+                return;
+            }
+            result = (|| {
+                let region = match current.take() {
+                    Some(region) if region.addr + region.owned == chunk_addr => region,
+                    region => {
+                        if let Some(region) = region {
+                            segments.push(region.finish(&tail, step, size)?);
+                        }
+                        RegionWriter::new(&self.dir, chunk_addr)?
+                    }
+                };
+                let region = current.insert(region);
+                region.out.write_all(&chunk[..owned])?;
+                region.owned += owned;
+                tail.clear();
+                tail.extend_from_slice(&chunk[owned..]);
+                Ok(())
+            })();
+        });
+        result?;
+        if let Some(region) = current {
+            segments.push(region.finish(&tail, step, size)?);
+        }
+        Ok(segments)
+    }
+
+    /// Values are compared with the ones in the given column of the
+    /// records. Region segments only have the values of the first pass
+    fn noninitial_scan(
+        &self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        column: usize,
+        report_progress: &mut Box<dyn FnMut(usize, usize)>,
+        dropped: &AtomicUsize,
+    ) -> io::Result<Vec<Segment>> {
+        let process = ctx.process.as_ref().unwrap();
+        let to_scan = self.len();
+        let next_segment = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let found = run_workers(
+            ctx.config.threads,
+            &scanned,
+            to_scan,
+            report_progress,
+            || {
+                let mut memory_reader = U::new(process);
+                let mut found = Vec::new();
+                loop {
+                    let idx = next_segment.fetch_add(1, Ordering::Relaxed);
+                    let Some(segment) = self.segments.get(idx) else {
+                        break Ok::<_, io::Error>(found);
+                    };
+                    let mut writer = RecordWriter::new(&self.dir)?;
+                    let evaluated = match &**segment {
+                        Segment::Records { file, len } => {
+                            let input = BufReader::with_capacity(IO_BUFFER_SIZE, file.open()?);
+                            self.filter_records(
+                                &mut memory_reader,
+                                expr,
+                                column,
+                                input,
+                                *len,
+                                &mut writer,
+                                &scanned,
+                            )?
+                        }
+                        Segment::Region {
+                            file, addr, owned, ..
+                        } => {
+                            let mut old = Vec::new();
+                            file.open()?.read_to_end(&mut old)?;
+                            Self::filter_region(
+                                &mut memory_reader,
+                                expr,
+                                *addr,
+                                *owned,
+                                &old,
+                                self.region_step,
+                                &mut writer,
+                                &scanned,
+                            )
+                        }
+                    };
+                    dropped.fetch_add(segment.len() - evaluated, Ordering::Relaxed);
+                    found.push((idx, writer.finish()?.into_iter().collect()));
+                }
+            },
+        );
+        report_progress(to_scan, to_scan);
+        Self::merge(found)
+    }
+
+    /// Returns how many records could be read from memory. The value
+    /// from the last pass moves to the values of earlier passes in
+    /// the records written.
+    #[allow(clippy::too_many_arguments)]
+    fn filter_records(
+        &self,
+        memory_reader: &mut U,
+        expr: &ScanExpr,
+        column: usize,
+        mut input: impl Read,
+        len: usize,
+        writer: &mut RecordWriter,
+        scanned: &AtomicUsize,
+    ) -> io::Result<usize> {
+        let size = mem::size_of::<T>();
+        let record_size = self.record_size();
+        let base = mem::size_of::<u64>() + column * size;
+        let mut evaluated = 0;
+        let mut buffer = Vec::new();
+        for batch in (0..len).step_by(PROGRESS_BATCH) {
+            let batch_len = PROGRESS_BATCH.min(len - batch);
+            read_records(&mut input, batch_len, record_size, &mut buffer)?;
+            let mut records = Vec::with_capacity(batch_len);
+            let mut vals = Vec::with_capacity(batch_len);
+            for record in buffer.chunks_exact(record_size) {
+                let (addr, _) = parse_record::<T>(record);
+                // Addresses that can't be read anymore are dropped
+                if let Some(val) = memory_reader.try_read::<T>(addr) {
+                    records.push((addr, record));
+                    vals.push((val, T::from_le_bytes(&record[base..base + size])));
+                }
+            }
+            evaluated += vals.len();
+            // Matches come in order, so their records are found by
+            // going along the ones that were read
+            let mut matched = records.iter();
+            let mut f_if_true = |val: T, addr: usize| {
+                let (_, record) = matched.find(|(old_addr, _)| *old_addr == addr).unwrap();
+                let (last, columns) = record[mem::size_of::<u64>()..].split_at(size);
+                let (earlier, snapshots) = columns.split_at(size * (self.passes - 1));
+                writer.push(addr, val, &[earlier, last, snapshots]);
+            };
+            let addrs = records.iter().map(|(addr, _)| *addr);
+            expr.eval_expr(&mut f_if_true, vals.into_iter(), addrs);
+            scanned.fetch_add(batch_len, Ordering::Relaxed);
+        }
+        Ok(evaluated)
+    }
+
+    /// Compare the current memory with a region segment, which becomes
+    /// the first pass. Returns how many values could be read from
+    /// memory
+    #[allow(clippy::too_many_arguments)]
+    fn filter_region(
+        memory_reader: &mut U,
+        expr: &ScanExpr,
+        region_addr: usize,
+        owned: usize,
+        old: &[u8],
+        step: usize,
+        writer: &mut RecordWriter,
+        scanned: &AtomicUsize,
+    ) -> usize {
+        let size = mem::size_of::<T>();
+        let mut evaluated = 0;
+        let mut f_if_true = |val: T, addr: usize| {
+            let offset = addr - region_addr;
+            writer.push(addr, val, &[&old[offset..offset + size]]);
+        };
+        memory_reader.read_region(
+            region_addr,
+            region_addr + owned,
+            region_addr + old.len(),
+            size - 1,
+            |chunk_addr, chunk, owned| {
+                let offsets = region_offsets(chunk_addr, owned, chunk.len(), step, size);
+                let vals = offsets.clone().map(|offset| {
+                    let old_offset = chunk_addr - region_addr + offset;
+                    (
+                        T::from_le_bytes(&chunk[offset..offset + size]),
+                        T::from_le_bytes(&old[old_offset..old_offset + size]),
+                    )
+                });
+                let addrs = offsets.clone().map(|offset| chunk_addr + offset);
+                expr.eval_expr(&mut f_if_true, vals, addrs);
+                let count = offsets.count();
+                evaluated += count;
+                scanned.fetch_add(count, Ordering::Relaxed);
+            },
+        );
+        evaluated
+    }
+
+    /// Column of the records holding the values baseline refers to,
+    /// the value from the last pass being column 0
+    fn baseline_column(&self, baseline: &Baseline) -> Result<usize, String> {
+        match baseline {
+            Baseline::Previous => Ok(0),
+            Baseline::First if self.passes > 1 => Ok(1),
+            Baseline::First => Ok(0),
+            Baseline::Pass(_) | Baseline::Snapshot(_) if self.passes == 0 => {
+                Err("there is nothing to compare to before the first scan".to_string())
+            }
+            Baseline::Pass(pass) if *pass == self.passes => Ok(0),
+            Baseline::Pass(pass) if (1..self.passes).contains(pass) => Ok(*pass),
+            Baseline::Pass(pass) => Err(format!(
+                "there is no pass {}, passes done: {}",
+                pass, self.passes
+            )),
+            Baseline::Snapshot(name) => match self.snapshots.iter().position(|n| n == name) {
+                Some(idx) => Ok(self.passes + idx),
+                None => Err(format!("there is no snapshot named {:?}", name)),
+            },
+        }
+    }
+
+    /// Copy records, putting the current values into the given column
+    /// or a new one at the end. Unreadable addresses keep the value
+    /// they had when scanned.
+    fn snapshot_records(
+        memory_reader: &mut U,
+        mut input: impl Read,
+        len: usize,
+        record_size: usize,
+        column: Option<usize>,
+        writer: &mut RecordWriter,
+    ) -> io::Result<()> {
+        let size = mem::size_of::<T>();
+        let mut buffer = Vec::new();
+        let mut current = vec![0; size];
+        for batch in (0..len).step_by(PROGRESS_BATCH) {
+            let batch_len = PROGRESS_BATCH.min(len - batch);
+            read_records(&mut input, batch_len, record_size, &mut buffer)?;
+            for record in buffer.chunks_exact(record_size) {
+                let (addr, val) = parse_record::<T>(record);
+                if memory_reader.read_bytes(addr, &mut current).is_err() {
+                    current.copy_from_slice(&record[mem::size_of::<u64>()..][..size]);
+                }
+                let columns = &record[mem::size_of::<u64>() + size..];
+                match column {
+                    Some(column) => {
+                        let (before, after) = columns.split_at((column - 1) * size);
+                        writer.push(addr, val, &[before, &current, &after[size..]]);
+                    }
+                    None => writer.push(addr, val, &[columns, &current]),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Put together segments from workers in the order of the work
+    /// parts they came from, which keeps them sorted by address
+    fn merge(found: Vec<io::Result<Vec<FoundSegments>>>) -> io::Result<Vec<Segment>> {
+        let mut parts = Vec::new();
+        for found in found {
+            parts.extend(found?);
+        }
+        parts.sort_unstable_by_key(|(idx, _)| *idx);
+        Ok(parts
+            .into_iter()
+            .flat_map(|(_, segments)| segments)
+            .collect())
+    }
+}
+
+impl<T, U> Addresses for AddrsDisk<T, U>
+where
+    T: FromLeBytes
+        + ToLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + ToString
+        + Send
+        + Sync
+        + 'static,
+    T::Err: Debug,
+    U: MemoryReader + 'static,
+    [(); mem::size_of::<T>()]:,
+{
+    fn new(process: &Process) -> Self {
+        Self {
+            segments: Vec::new(),
+            passes: 0,
+            snapshots: Vec::new(),
+            dir: PathBuf::new(),
+            region_step: 1,
+            memory_reader: U::new(process),
+            _type: std::marker::PhantomData,
+        }
+    }
+
+    fn get_type(&self) -> String {
+        format!("{} (results on disk)", any::type_name::<T>())
+    }
+
+    fn value_size(&self) -> usize {
+        mem::size_of::<T>()
+    }
+
+    fn passes(&self) -> usize {
+        self.passes
+    }
+
+    fn len(&self) -> usize {
+        self.segments.iter().map(|segment| segment.len()).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn scan(
+        &mut self,
+        ctx: &Context,
+        expr: &ScanExpr,
+        baseline: &Baseline,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> Result<usize, String> {
+        let column = self.baseline_column(baseline)?;
+        self.dir = ctx.config.spill_dir.clone();
+        let dropped = AtomicUsize::new(0);
+        let segments = if self.passes > 0 {
+            self.noninitial_scan(ctx, expr, column, &mut report_progress, &dropped)
+        } else {
+            self.region_step = ctx.config.alignment.step(mem::size_of::<T>());
+            self.initial_scan(ctx, expr, &mut report_progress)
+        };
+        let segments = segments.map_err(|e| format!("couldn't store results on disk: {}", e))?;
+        self.segments = segments.into_iter().map(Arc::new).collect();
+        self.passes += 1;
+        Ok(dropped.into_inner())
+    }
+
+    fn snapshot(&mut self, name: &str) -> Result<(), String> {
+        if self.passes == 0 {
+            return Err("there is nothing to snapshot before the first scan".to_string());
+        }
+        let column = self
+            .snapshots
+            .iter()
+            .position(|n| n == name)
+            .map(|idx| self.passes + idx);
+        let record_size = self.record_size();
+        let mut segments = Vec::with_capacity(self.segments.len());
+        for segment in &self.segments {
+            let Segment::Records { file, len } = &**segment else {
+                return Err(
+                    "unknown values have to be filtered before taking a snapshot".to_string(),
+                );
+            };
+            let written = file.open().and_then(|input| {
+                let input = BufReader::with_capacity(IO_BUFFER_SIZE, input);
+                let mut writer = RecordWriter::new(&self.dir)?;
+                Self::snapshot_records(
+                    &mut self.memory_reader,
+                    input,
+                    *len,
+                    record_size,
+                    column,
+                    &mut writer,
+                )?;
+                writer.finish()
+            });
+            let written = written.map_err(|e| format!("couldn't store results on disk: {}", e))?;
+            segments.extend(written.map(Arc::new));
+        }
+        self.segments = segments;
+        if column.is_none() {
+            self.snapshots.push(name.to_string());
+        }
+        Ok(())
+    }
+
+    fn snapshot_names(&self) -> Vec<String> {
+        self.snapshots.clone()
+    }
+
+    fn get_addrs(&self) -> Result<Vec<usize>, String> {
+        self.read_first(usize::MAX).map(|(addrs, _)| addrs)
+    }
+
+    fn get_addr(&self, idx: usize) -> Result<usize, String> {
+        self.read_record(idx).map(|(addr, _)| addr)
+    }
+
+    fn try_clone_box(&self) -> Result<Box<dyn Addresses>, String> {
+        Ok(Box::new(Self {
+            segments: self.segments.clone(),
+            passes: self.passes,
+            snapshots: self.snapshots.clone(),
+            dir: self.dir.clone(),
+            region_step: self.region_step,
+            memory_reader: self.memory_reader.clone(),
+            _type: std::marker::PhantomData,
+        }))
+    }
+
+    fn get_vals(&self) -> Result<Vec<String>, String> {
+        let (_, vals) = self.read_first(usize::MAX)?;
+        Ok(vals.iter().map(|v| v.to_string()).collect())
+    }
+
+    fn get_vals_to_print(&mut self, max: usize) -> Result<Vec<(usize, String, String)>, String> {
+        let (addrs, vals) = self.read_first(max)?;
+        Ok(addrs
+            .into_iter()
+            .zip(vals)
+            .map(|(addr, val)| {
+                let current = match self.memory_reader.try_read::<T>(addr) {
+                    Some(current) => current.to_string(),
+                    None => "??".to_string(),
+                };
+                (addr, val.to_string(), current)
+            })
+            .collect())
+    }
+
+    fn write(&mut self, value: &str, addr_idx: usize) -> Result<(), String> {
+        let addr = self.get_addr(addr_idx)?;
+        let value = value
+            .parse::<T>()
+            .map_err(|e| format!("couldn't parse {:?}: {:?}", value, e))?;
+        self.memory_reader
+            .write(addr, value)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addresses::FloatCmp;
+    use crate::memory_reader::MemoryReaderVm;
+
+    fn scan(addrs: &mut dyn Addresses, ctx: &Context, expr: ScanExpr) -> usize {
+        addrs
+            .scan(ctx, &expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap()
+    }
+
+    #[test]
+    fn addrs_disk_scan() {
+        let mut values = vec![0xd15c_u32; 50_000];
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        ctx.config.threads = 4;
+        let process = ctx.process.as_ref().unwrap();
+        let mut addrs = AddrsDisk::<u32, MemoryReaderVm>::new(process);
+        let expr = ScanExpr::Equal(values[0].to_string(), FloatCmp::default());
+        scan(&mut addrs, &ctx, expr);
+        let found = addrs.get_addrs().unwrap();
+        assert_eq!(found.len(), addrs.len());
+        assert!(found.windows(2).all(|w| w[0] < w[1]));
+        for value in values.iter() {
+            assert!(found.binary_search(&(value as *const u32 as usize)).is_ok());
+        }
+
+        let copy = addrs.try_clone_box().unwrap();
+        values[7] = std::hint::black_box(0xd15d);
+        let addr = &values[7] as *const u32 as usize;
+        scan(&mut addrs, &ctx, ScanExpr::Increased);
+        let idx = addrs
+            .get_addrs()
+            .unwrap()
+            .iter()
+            .position(|&a| a == addr)
+            .unwrap();
+        assert_eq!(addrs.get_vals().unwrap()[idx], 0xd15d.to_string());
+        assert!(!addrs
+            .get_addrs()
+            .unwrap()
+            .contains(&(&values[8] as *const u32 as usize)));
+        assert!(copy
+            .get_addrs()
+            .unwrap()
+            .contains(&(&values[8] as *const u32 as usize)));
+
+        assert_eq!(addrs.get_addr(idx), Ok(addr));
+        assert!(addrs.get_addr(addrs.len()).is_err());
+        addrs.write("3", idx).unwrap();
+        assert_eq!(std::hint::black_box(values[7]), 3);
+        let snapshot = Baseline::Snapshot("nope".to_string());
+        assert!(addrs
+            .scan(&ctx, &ScanExpr::Changed, &snapshot, Box::new(|_, _| ()))
+            .is_err());
+    }
+
+    #[test]
+    fn addrs_disk_baselines() {
+        let mut values = vec![0xba5e_u32; 1000];
+        let addr = &values[10] as *const u32 as usize;
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let process = ctx.process.as_ref().unwrap();
+        let mut addrs = AddrsDisk::<u32, MemoryReaderVm>::new(process);
+        let keeps = |addrs: &mut AddrsDisk<_, _>, expr, baseline| {
+            addrs
+                .scan(&ctx, &expr, &baseline, Box::new(|_, _| ()))
+                .map(|_| addrs.get_addrs().unwrap().contains(&addr))
+        };
+        assert!(keeps(&mut addrs, ScanExpr::Unknown, Baseline::Pass(1)).is_err());
+        let expr = ScanExpr::Equal(values[0].to_string(), FloatCmp::default());
+        assert_eq!(keeps(&mut addrs, expr, Baseline::Previous), Ok(true));
+        addrs.snapshot("start").unwrap();
+        assert_eq!(addrs.snapshot_names(), ["start"]);
+
+        values[10] = std::hint::black_box(0xba5f);
+        let changed = ScanExpr::Changed;
+        assert_eq!(keeps(&mut addrs, changed, Baseline::Previous), Ok(true));
+        values[10] = std::hint::black_box(0xba5e);
+        let same = || ScanExpr::NotChanged;
+        assert_eq!(keeps(&mut addrs, same(), Baseline::First), Ok(true));
+        assert_eq!(keeps(&mut addrs, same(), Baseline::Pass(1)), Ok(true));
+        let start = Baseline::Snapshot("start".to_string());
+        assert_eq!(keeps(&mut addrs, same(), start.clone()), Ok(true));
+        let decreased = ScanExpr::Decreased;
+        assert_eq!(keeps(&mut addrs, decreased, Baseline::Pass(2)), Ok(true));
+        assert!(keeps(&mut addrs, same(), Baseline::Pass(9)).is_err());
+
+        // Taking a snapshot again replaces it, also in clones
+        values[10] = std::hint::black_box(0xba60);
+        addrs.snapshot("start").unwrap();
+        let copy = addrs.try_clone_box().unwrap();
+        assert_eq!(copy.snapshot_names(), ["start"]);
+        assert_eq!(keeps(&mut addrs, same(), start), Ok(true));
+        assert_eq!(addrs.passes(), 7);
+    }
+
+    #[test]
+    fn addrs_disk_unknown() {
+        let mut values = vec![0xd15c_u16; 10_000];
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        ctx.config.spill_dir = std::env::temp_dir().join(format!("memori-{}", process::id()));
+        std::fs::create_dir_all(&ctx.config.spill_dir).unwrap();
+        let process = ctx.process.as_ref().unwrap();
+        let mut addrs = AddrsDisk::<u16, MemoryReaderVm>::new(process);
+        scan(&mut addrs, &ctx, ScanExpr::Unknown);
+        assert!(addrs.len() >= values.len());
+        assert!(addrs.get_addr(0).is_err());
+        assert!(addrs.get_vals_to_print(10).is_err());
+        let files: Vec<_> = addrs
+            .segments
+            .iter()
+            .map(|segment| match &**segment {
+                Segment::Records { file, .. } | Segment::Region { file, .. } => file.path.clone(),
+            })
+            .collect();
+        assert!(files.iter().all(|path| path.exists()));
+        assert!(files
+            .iter()
+            .all(|path| path.starts_with(&ctx.config.spill_dir)));
+
+        values[4321] = std::hint::black_box(0xd15a);
+        let addr = &values[4321] as *const u16 as usize;
+        let copy = addrs.try_clone_box().unwrap();
+        scan(
+            &mut addrs,
+            &ctx,
+            ScanExpr::DecreasedBy("2".to_string(), FloatCmp::Exact),
+        );
+        let found = addrs.get_addrs().unwrap();
+        assert!(found.windows(2).all(|w| w[0] < w[1]));
+        let idx = found.binary_search(&addr).unwrap();
+        assert_eq!(addrs.get_addr(idx), Ok(addr));
+        assert_eq!(
+            addrs.get_vals_to_print(idx + 1).unwrap()[idx].1,
+            0xd15a.to_string()
+        );
+        assert_eq!(
+            addrs.get_vals_to_print(3).unwrap().len(),
+            3.min(found.len())
+        );
+        let first = Baseline::First;
+        let expr = ScanExpr::DecreasedBy("2".to_string(), FloatCmp::Exact);
+        addrs
+            .scan(&ctx, &expr, &first, Box::new(|_, _| ()))
+            .unwrap();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+
+        // Region files are gone with the last result set using them
+        assert!(files.iter().all(|path| path.exists()));
+        drop((copy, addrs));
+        assert!(files.iter().all(|path| !path.exists()));
+        std::fs::remove_dir_all(&ctx.config.spill_dir).unwrap();
+    }
+}
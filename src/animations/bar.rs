@@ -49,6 +49,7 @@ enum GOLCell {
     Alive,
 }
 
+#[allow(clippy::needless_range_loop)]
 pub fn game_of_life(progress_recv: Receiver<(usize, usize)>) {
     let board_size_x = 20;
     let board_size_y = 10;
@@ -0,0 +1,206 @@
+use roxmltree::{Document, Node};
+
+use crate::commands::ValType;
+use crate::table::{Table, TableEntry};
+
+/// Translate a Cheat Engine table (`.CT`) into one of ours. Entries
+/// that have no equivalent, like Auto Assembler scripts, are left out
+/// and described in the returned list. Entries of groups are imported
+/// as if they weren't in one.
+pub fn import_ct(xml: &str) -> Result<(Table, Vec<String>), String> {
+    let document = Document::parse(xml).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("CheatTable") {
+        return Err("not a Cheat Engine table".to_string());
+    }
+    let mut table = Table::default();
+    let mut problems = Vec::new();
+    if let Some(entries) = child(root, "CheatEntries") {
+        import_entries(entries, &mut table, &mut problems);
+    }
+    Ok((table, problems))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn import_entries(entries: Node, table: &mut Table, problems: &mut Vec<String>) {
+    for entry in entries
+        .children()
+        .filter(|child| child.has_tag_name("CheatEntry"))
+    {
+        // Descriptions are quoted
+        let label = child_text(entry, "Description")
+            .unwrap_or_default()
+            .trim_matches('"')
+            .to_string();
+        let is_group = child_text(entry, "GroupHeader") == Some("1");
+        if !is_group || child(entry, "Address").is_some() {
+            match import_entry(entry, label.clone()) {
+                Ok(table_entry) => table.entries.push(table_entry),
+                Err(err) => problems.push(format!("{}: {}", label, err)),
+            }
+        }
+        if let Some(children) = child(entry, "CheatEntries") {
+            import_entries(children, table, problems);
+        }
+    }
+}
+
+fn import_entry(entry: Node, label: String) -> Result<TableEntry, String> {
+    let variable_type = child_text(entry, "VariableType").unwrap_or_default();
+    let signed = child_text(entry, "ShowAsSigned") == Some("1");
+    let number = |val_type: ValType| Ok((val_type, None));
+    let length = |name| -> Result<usize, String> {
+        child_text(entry, name)
+            .and_then(|length| length.parse().ok())
+            .ok_or(format!("{} without {}", variable_type, name))
+    };
+    let (val_type, size) = match variable_type {
+        "Byte" if signed => number(ValType::I8),
+        "Byte" => number(ValType::U8),
+        "2 Bytes" if signed => number(ValType::I16),
+        "2 Bytes" => number(ValType::U16),
+        "4 Bytes" if signed => number(ValType::I32),
+        "4 Bytes" => number(ValType::U32),
+        "8 Bytes" if signed => number(ValType::I64),
+        "8 Bytes" => number(ValType::U64),
+        "Float" => number(ValType::F32),
+        "Double" => number(ValType::F64),
+        // The length is counted in characters
+        "String" if child_text(entry, "Unicode") == Some("1") => {
+            Ok((ValType::Utf16, Some(length("Length")? * 2)))
+        }
+        "String" => Ok((ValType::Utf8, Some(length("Length")?))),
+        "Array of byte" => Ok((ValType::Aob, Some(length("ByteLength")?))),
+        "" => Err("entry has no type".to_string()),
+        _ => Err(format!("{} entries aren't supported", variable_type)),
+    }?;
+
+    let address = child_text(entry, "Address").ok_or("entry has no address")?;
+    // Cheat Engine lists offsets starting with the one applied last
+    let offsets: Vec<_> = match child(entry, "Offsets") {
+        Some(offsets) => offsets
+            .children()
+            .filter(|offset| offset.has_tag_name("Offset"))
+            .map(|offset| offset.text().unwrap_or_default().trim())
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut address = address.to_string();
+    for offset in offsets.iter().rev() {
+        address = format!("[{}]+{}", address, offset);
+    }
+
+    let last_state = child(entry, "LastState");
+    let frozen = last_state.and_then(|state| state.attribute("Activated")) == Some("1");
+    let value = frozen
+        .then(|| last_state.and_then(|state| state.attribute("Value")))
+        .flatten()
+        .map(str::to_string);
+    Ok(TableEntry {
+        label,
+        address,
+        val_type,
+        size,
+        frozen,
+        value,
+        freeze_mode: None,
+        description: String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address_expr::AddressExpr;
+
+    const TABLE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<CheatTable CheatEngineTableVersion="45">
+  <CheatEntries>
+    <CheatEntry>
+      <ID>0</ID>
+      <Description>"Health"</Description>
+      <ShowAsSigned>1</ShowAsSigned>
+      <VariableType>4 Bytes</VariableType>
+      <Address>"game.exe"+1A2B0</Address>
+      <Offsets>
+        <Offset>40</Offset>
+        <Offset>18</Offset>
+      </Offsets>
+      <LastState Value="100" Activated="1" RealAddress="7FF6A0001040"/>
+    </CheatEntry>
+    <CheatEntry>
+      <ID>1</ID>
+      <Description>"Player"</Description>
+      <GroupHeader>1</GroupHeader>
+      <CheatEntries>
+        <CheatEntry>
+          <ID>2</ID>
+          <Description>"Name"</Description>
+          <VariableType>String</VariableType>
+          <Length>16</Length>
+          <Unicode>1</Unicode>
+          <Address>1C0F00A0</Address>
+        </CheatEntry>
+        <CheatEntry>
+          <ID>3</ID>
+          <Description>"Speed"</Description>
+          <VariableType>Float</VariableType>
+          <Address>game.exe+2000</Address>
+        </CheatEntry>
+      </CheatEntries>
+    </CheatEntry>
+    <CheatEntry>
+      <ID>4</ID>
+      <Description>"Infinite ammo"</Description>
+      <VariableType>Auto Assembler Script</VariableType>
+      <AssemblerScript>[ENABLE]
+nop</AssemblerScript>
+    </CheatEntry>
+    <CheatEntry>
+      <ID>5</ID>
+      <Description>"Bytes"</Description>
+      <VariableType>Array of byte</VariableType>
+      <Address>game.exe+3000</Address>
+    </CheatEntry>
+  </CheatEntries>
+</CheatTable>
+"#;
+
+    #[test]
+    fn import_cheat_engine_table() {
+        let (table, problems) = import_ct(TABLE).unwrap();
+        let labels: Vec<_> = table
+            .entries
+            .iter()
+            .map(|entry| entry.label.as_str())
+            .collect();
+        assert_eq!(labels, ["Health", "Name", "Speed"]);
+
+        let health = &table.entries[0];
+        assert_eq!(health.val_type, ValType::I32);
+        assert_eq!(health.address, "[[\"game.exe\"+1A2B0]+18]+40");
+        assert!(health.address.parse::<AddressExpr>().is_ok());
+        assert!(health.frozen);
+        assert_eq!(health.value.as_deref(), Some("100"));
+
+        let name = &table.entries[1];
+        assert_eq!((name.val_type, name.size), (ValType::Utf16, Some(32)));
+        assert!(!table.entries[2].frozen);
+
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("Infinite ammo: Auto Assembler Script"));
+        assert!(problems[1].starts_with("Bytes: "));
+
+        assert!(import_ct("<CheatTable>").is_err());
+        assert!(import_ct("<Table/>").is_err());
+    }
+}
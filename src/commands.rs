@@ -1,4 +1,7 @@
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None, multicall = true, override_usage = "<COMMAND>")]
@@ -25,6 +28,19 @@ pub enum Command {
     #[clap(visible_alias("p"))]
     Print,
 
+    /// Go back to the results from before the last filter
+    Undo,
+
+    /// Reapply the last undone filter
+    Redo,
+
+    /// List filter passes that can be undone or redone
+    History,
+
+    /// Save current values of the found addresses to filter against
+    /// them later with --baseline
+    Snapshot(SnapshotArgs),
+
     /// Add address to selected
     #[clap(visible_alias("s"))]
     Select(SelectArgs),
@@ -33,18 +49,40 @@ pub enum Command {
     #[clap(visible_alias("u"), visible_alias("uns"))]
     Unselect(UnselectArgs),
 
+    /// List selected addresses
+    #[clap(visible_alias("ls"))]
+    Selected,
+
     /// Set selected address to value
     Set(SetArgs),
 
     /// Freeze selected address so the value doesn't change
     Freeze(FreezeArgs),
 
+    /// Stop freezing an address
+    Unfreeze(UnfreezeArgs),
+
+    /// List frozen addresses
+    Frozen,
+
+    /// Find chains of pointers from modules to an address, which
+    /// still lead to it after restarting the process
+    #[clap(name = "pointerscan")]
+    PointerScan(PointerScanArgs),
+
+    /// Save selected addresses to a cheat table or load them from one
+    Table(TableArgs),
+
+    /// Change settings
+    Config(ConfigArgs),
+
     /// Exit the program
     #[clap(visible_alias("quit"))]
     Exit,
 }
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ValType {
     I128,
     U128,
@@ -56,6 +94,14 @@ pub enum ValType {
     U16,
     I8,
     U8,
+    F64,
+    F32,
+    /// Array of bytes, searched for with patterns like `48 8B ?? 0?`
+    Aob,
+    Ascii,
+    Utf8,
+    /// UTF-16, little endian
+    Utf16,
 }
 
 #[derive(Debug, Args)]
@@ -68,6 +114,7 @@ pub struct ProcessArgs {
     pub pid: u32,
 }
 
+/// Operators understood by the filter expression parser
 #[derive(Debug, Clone, ValueEnum)]
 pub enum FilterOperator {
     /// <
@@ -88,36 +135,271 @@ pub enum FilterOperator {
     /// !=
     #[value(alias("!="), hide = false)]
     NotEqual,
+    /// Between two operands, the lower and the upper bound
+    Between,
+    /// Not between two operands, the lower and the upper bound
+    NotBetween,
     Changed,
     NotChanged,
+    /// Bigger than on the previous scan
+    #[value(alias("inc"))]
+    Increased,
+    /// Smaller than on the previous scan
+    #[value(alias("dec"))]
+    Decreased,
+    /// Bigger than on the previous scan by exactly the operand
+    #[value(alias("inc-by"))]
+    IncreasedBy,
+    /// Smaller than on the previous scan by exactly the operand
+    #[value(alias("dec-by"))]
+    DecreasedBy,
+    /// Bigger than on the previous scan by at least operand percent
+    #[value(alias("inc-by-percent"))]
+    IncreasedByPercent,
+    /// Smaller than on the previous scan by at least operand percent
+    #[value(alias("dec-by-percent"))]
+    DecreasedByPercent,
     Unknown,
 }
 
+/// How floating-point values are matched by == and !=
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Rounding {
+    /// Bit-for-bit comparison
+    Exact,
+    /// Round to as many decimal places as the operand has
+    Rounded,
+    /// Truncate to as many decimal places as the operand has
+    Truncated,
+    /// Anything less than one unit of the operand's last decimal
+    /// place away, e.g. 100 matches everything between 99 and 101
+    ExtremeRounded,
+}
+
 #[derive(Debug, Args)]
 pub struct FilterArgs {
-    pub operator: FilterOperator,
-    pub operand: Option<String>,
+    /// Operators with their operands, optionally combined with and, or,
+    /// not and parentheses, e.g. `> 10 and < 500 and changed`
+    #[arg(required = true, num_args = 1.., allow_negative_numbers = true)]
+    pub expr: Vec<String>,
+
+    /// Maximum allowed difference for float (in)equality. Takes
+    /// precedence over --rounding
+    #[arg(long, short)]
+    pub epsilon: Option<f64>,
+
+    /// Rounding mode for float (in)equality [default: rounded]
+    #[arg(long, short, value_enum)]
+    pub rounding: Option<Rounding>,
+
+    /// Exclude the bounds themselves from between and not-between
+    #[arg(long, short = 'x')]
+    pub exclusive: bool,
+
+    /// What changed, increased etc. compare to: `previous` pass,
+    /// `first` scan, pass number or name of a snapshot
+    #[arg(long, short)]
+    pub baseline: Option<String>,
+
+    /// Match strings regardless of the case of ASCII letters
+    #[arg(long, short)]
+    pub ignore_case: bool,
+
+    /// Match strings that start with the text instead of ending
+    /// right after it
+    #[arg(long, short)]
+    pub prefix: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    /// Name to save the current values under. Lists snapshots if
+    /// omitted
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Args)]
 pub struct SelectArgs {
+    /// Index of the address in the scan results
     pub to_select: usize,
+    /// Name to remember the address by
+    pub label: Option<String>,
 }
 
 #[derive(Debug, Args)]
 pub struct UnselectArgs {
+    /// Index in the list of selected addresses
     pub to_unselect: usize,
 }
 
 #[derive(Debug, Args)]
 pub struct SetArgs {
+    /// Index in the list of selected addresses
     pub selected: usize,
     pub value: String,
 }
 
+/// Parse a hex number, with or without `0x` in front
+fn parse_hex(value: &str) -> Result<usize, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    usize::from_str_radix(digits, 16).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Args)]
+pub struct PointerScanArgs {
+    /// Address to find chains to, like `7ffd1000` or `[game+1A2B0]+18`
+    pub addr: String,
+
+    /// Maximum number of pointers in a chain
+    #[arg(long, short, default_value_t = 3)]
+    pub depth: usize,
+
+    /// Maximum offset added to a pointer to get to the next one, in
+    /// hex
+    #[arg(long, short, value_parser = parse_hex, default_value = "1000")]
+    pub max_offset: usize,
+
+    /// Stop after finding this many chains
+    #[arg(long, default_value_t = 100)]
+    pub max_results: usize,
+
+    /// Save the pointer map with the address to a file, to check the
+    /// chains of later runs against it with --intersect
+    #[arg(long, short)]
+    pub save: Option<PathBuf>,
+
+    /// Only keep chains that also led to the address in the run whose
+    /// pointer map was saved to this file. Can be repeated
+    #[arg(long, short)]
+    pub intersect: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FreezeMode {
+    /// Always write the frozen value back
+    Exact,
+    /// Let the value grow, but don't let it go below the frozen one
+    Increase,
+    /// Let the value shrink, but don't let it go above the frozen one
+    Decrease,
+}
+
 #[derive(Debug, Args)]
 pub struct FreezeArgs {
+    /// Index in the list of selected addresses
     pub selected: usize,
+    /// Value to freeze at. Defaults to the current one
+    pub value: Option<String>,
+    #[arg(long, short, value_enum, default_value_t = FreezeMode::Exact)]
+    pub mode: FreezeMode,
+}
+
+#[derive(Debug, Args)]
+pub struct UnfreezeArgs {
+    /// Index in the list of frozen addresses
+    pub frozen: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct TableArgs {
+    #[command(subcommand)]
+    pub action: TableAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TableAction {
+    /// Write the selected addresses and what's frozen to a TOML file
+    Save { file: PathBuf },
+    /// Select and freeze the entries of a TOML file
+    Load { file: PathBuf },
+    /// Select and freeze the entries of a Cheat Engine .CT table
+    Import { file: PathBuf },
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub setting: ConfigSetting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReaderKind {
+    /// Reads and writes through /proc/pid/mem
+    Simple,
+    /// Reads and writes with process_vm_readv/process_vm_writev
+    Vm,
+}
+
+/// How scan results are kept in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StorageKind {
+    /// An address and a value per hit, fastest
+    Simple,
+    /// Per-region offsets or bitmaps, much smaller for lots of hits
+    Compact,
+}
+
+/// Alignment of the addresses checked during the first scan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Alignment {
+    #[value(name = "1")]
+    One,
+    #[value(name = "2")]
+    Two,
+    #[value(name = "4")]
+    Four,
+    #[value(name = "8")]
+    Eight,
+    /// Aligned to the size of the scanned type, a.k.a. fast scan
+    #[default]
+    Natural,
+}
+
+impl Alignment {
+    /// Distance between consecutive scanned addresses for a type of
+    /// the given size
+    pub fn step(&self, type_size: usize) -> usize {
+        match self {
+            Alignment::One => 1,
+            Alignment::Two => 2,
+            Alignment::Four => 4,
+            Alignment::Eight => 8,
+            Alignment::Natural => type_size,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigSetting {
+    /// How often frozen values are written back, in milliseconds
+    FreezeInterval {
+        #[arg(value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+        millis: u64,
+    },
+    /// How many filter passes can be undone. 0 turns off keeping
+    /// copies of old result sets
+    History { len: usize },
+    /// Results that could take more than this many MiB of memory are
+    /// kept on disk. Takes effect on the next initial scan
+    DiskThreshold { mib: usize },
+    /// Directory results kept on disk are written to. Takes effect on
+    /// the next scan
+    SpillDir { path: PathBuf },
+    /// How results are kept in memory. Takes effect on the next type
+    /// change
+    Storage { kind: StorageKind },
+    /// Memory reader used by the scanner. Takes effect on the next
+    /// type change
+    Reader { kind: ReaderKind },
+    /// Alignment of scanned addresses. Lower alignment finds values
+    /// in packed structs but is slower and finds more garbage
+    Alignment { alignment: Alignment },
+    /// Number of threads used for scanning
+    Threads {
+        #[arg(value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        count: usize,
+    },
 }
 
 impl Cli {
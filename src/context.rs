@@ -1,14 +1,177 @@
-use crate::addresses::{Addresses, AddrsSimple};
-use crate::commands::{ProcessArgs, TypeArgs, ValType};
-use crate::memory_reader::{MemoryReader, MemoryReaderSimple};
-use crate::process::Process;
+use crate::address_expr::AddressExpr;
+use crate::addresses::{Addresses, AddrsSimple, ApproxEq, Delta, ScanExpr};
+use crate::addresses_bytes::AddrsBytes;
+use crate::addresses_compact::AddrsCompact;
+use crate::addresses_disk::AddrsDisk;
+use crate::cheat_engine;
+use crate::commands::{
+    Alignment, ConfigArgs, ConfigSetting, FreezeArgs, FreezeMode, PointerScanArgs, ProcessArgs,
+    ReaderKind, SelectArgs, SetArgs, StorageKind, TypeArgs, UnfreezeArgs, UnselectArgs, ValType,
+};
+use crate::freezer::{Freezer, FrozenEntry};
+use crate::history::History;
+use crate::memory_reader::{
+    FromLeBytes, MemoryReader, MemoryReaderSimple, MemoryReaderVm, ToLeBytes,
+};
+use crate::pointer_scan::{PointerChain, PointerMap, SavedPointerMap};
+use crate::process::{Module, Process};
+use crate::table::{Table, TableEntry};
+use crate::val_type::with_val_type;
 
+use std::fmt::Debug;
+use std::fs;
 use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+pub struct Config {
+    pub alignment: Alignment,
+    /// Scans whose results could take more than this many bytes of
+    /// memory keep them on disk instead
+    pub disk_threshold: usize,
+    /// Where results kept on disk go. /var/tmp is on disk, unlike
+    /// /tmp, which often is a tmpfs that would keep them in memory
+    pub spill_dir: PathBuf,
+    pub freeze_interval: Duration,
+    /// How many filter passes can be undone
+    pub history_len: usize,
+    pub reader: ReaderKind,
+    pub storage: StorageKind,
+    pub threads: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            alignment: Alignment::default(),
+            disk_threshold: 4 * 1024 * 1024 * 1024,
+            spill_dir: PathBuf::from("/var/tmp"),
+            freeze_interval: Duration::from_millis(100),
+            history_len: 10,
+            reader: ReaderKind::Simple,
+            storage: StorageKind::Simple,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// Most memory the results of an initial scan with expr can take in
+/// the configured storage, up to and including the first filter
+/// after it. That's when an unknown scan's copy of the memory turns
+/// into every address with its value.
+fn estimate_results_size<T>(proc: &Process, config: &Config, expr: &ScanExpr) -> usize {
+    let size = mem::size_of::<T>();
+    let readable: usize = proc
+        .memory_maps
+        .iter()
+        .filter(|mm| mm.perms.read)
+        .map(|mm| mm.addr_end - mm.addr_start)
+        .sum();
+    let addrs = readable / config.alignment.step(size);
+    match config.storage {
+        // The address, the value and the value of the first pass,
+        // which is kept as a baseline. The copy of an unknown scan is
+        // only let go of after the first filter.
+        StorageKind::Simple => {
+            let copy = if matches!(expr, ScanExpr::Unknown) { readable } else { 0 };
+            addrs * (mem::size_of::<usize>() + 2 * size) + copy
+        }
+        // A bit per address in the bitmaps and the value
+        StorageKind::Compact => addrs.div_ceil(8) + addrs * size,
+    }
+}
+
+/// Results are kept on disk if expr is given and could find more than
+/// `Config::disk_threshold` bytes of them
+fn new_addrs<T>(proc: &Process, config: &Config, expr: Option<&ScanExpr>) -> Box<dyn Addresses>
+where
+    T: FromLeBytes
+        + ToLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + ToString
+        + Send
+        + Sync
+        + 'static,
+    T::Err: Debug,
+    [(); mem::size_of::<T>()]:,
+{
+    match config.reader {
+        ReaderKind::Simple => new_addrs_with_reader::<T, MemoryReaderSimple>(proc, config, expr),
+        ReaderKind::Vm => new_addrs_with_reader::<T, MemoryReaderVm>(proc, config, expr),
+    }
+}
+
+fn new_addrs_with_reader<T, U>(
+    proc: &Process,
+    config: &Config,
+    expr: Option<&ScanExpr>,
+) -> Box<dyn Addresses>
+where
+    T: FromLeBytes
+        + ToLeBytes
+        + Debug
+        + FromStr
+        + Copy
+        + PartialOrd
+        + ApproxEq
+        + Delta
+        + ToString
+        + Send
+        + Sync
+        + 'static,
+    T::Err: Debug,
+    U: MemoryReader + 'static,
+    [(); mem::size_of::<T>()]:,
+{
+    let estimate = |expr| estimate_results_size::<T>(proc, config, expr);
+    if expr.is_some_and(|expr| estimate(expr) > config.disk_threshold) {
+        return Box::new(AddrsDisk::<T, U>::new(proc));
+    }
+    match config.storage {
+        StorageKind::Simple => Box::new(AddrsSimple::<T, U>::new(proc)),
+        StorageKind::Compact => Box::new(AddrsCompact::<T, U>::new(proc)),
+    }
+}
+
+fn new_bytes_addrs(proc: &Process, config: &Config, val_type: ValType) -> Box<dyn Addresses> {
+    match config.reader {
+        ReaderKind::Simple => Box::new(AddrsBytes::<MemoryReaderSimple>::with_type(proc, val_type)),
+        ReaderKind::Vm => Box::new(AddrsBytes::<MemoryReaderVm>::with_type(proc, val_type)),
+    }
+}
+
+/// An address the user picked out of the scan results. It's kept
+/// with its own type, so it stays valid after changing the scanned
+/// type or filtering again.
+#[derive(Debug, Clone)]
+pub struct WatchEntry {
+    pub addr: usize,
+    /// How the address was given, kept to save it in tables
+    pub expr: AddressExpr,
+    pub val_type: ValType,
+    /// Size of the value in bytes
+    pub size: usize,
+    pub label: String,
+    pub description: String,
+}
 
 pub struct Context {
     pub quit: bool,
     pub process: Option<Process>,
     pub addrs: Option<Box<dyn Addresses>>,
+    pub history: History,
+    pub val_type: Option<ValType>,
+    pub freezer: Option<Freezer>,
+    pub watch_list: Vec<WatchEntry>,
+    pub config: Config,
 }
 
 impl Default for Context {
@@ -23,12 +186,23 @@ impl Context {
             process: None,
             quit: false,
             addrs: None,
+            history: History::new(String::new()),
+            val_type: None,
+            freezer: None,
+            watch_list: Vec::new(),
+            config: Config::default(),
         }
     }
 
     pub fn process(&mut self, args: &ProcessArgs) -> io::Result<()> {
         match Process::try_new(args.pid) {
             Ok(proc) => {
+                // Frozen and selected addresses belong to the old process
+                self.watch_list.clear();
+                self.freezer = Some(Freezer::new::<MemoryReaderSimple>(
+                    &proc,
+                    self.config.freeze_interval,
+                ));
                 self.process = Some(proc);
                 Ok(())
             }
@@ -38,36 +212,313 @@ impl Context {
 
     pub fn change_type(&mut self, args: &TypeArgs) {
         let proc = &self.process.as_ref().unwrap();
-        match args.val_type {
-            ValType::I128 => {
-                self.addrs = Some(Box::new(AddrsSimple::<i128, MemoryReaderSimple>::new(proc)));
+        self.val_type = Some(args.val_type);
+        self.addrs = Some(with_val_type!(
+            args.val_type,
+            new_addrs(proc, &self.config, None),
+            bytes => new_bytes_addrs(proc, &self.config, args.val_type)
+        ));
+        self.history = History::new(format!("type {}", args.val_type.name()));
+    }
+
+    /// Move the results of an initial scan to disk before it if they
+    /// could take more than `disk_threshold` bytes. Later filters only
+    /// ever keep fewer of them.
+    pub fn prepare_scan(&mut self, expr: &ScanExpr) {
+        let (Some(proc), Some(addrs), Some(val_type)) = (&self.process, &self.addrs, self.val_type)
+        else {
+            return;
+        };
+        if addrs.passes() > 0 {
+            return;
+        }
+        self.addrs = Some(with_val_type!(
+            val_type,
+            new_addrs(proc, &self.config, Some(expr)),
+            bytes => return
+        ));
+    }
+
+    /// Go back to the result set from before the last filter pass.
+    /// Returns its length
+    pub fn undo(&mut self) -> Result<usize, String> {
+        let addrs = self.addrs.as_mut().ok_or("You have to select a type first")?;
+        match self.history.undo(addrs) {
+            true => Ok(addrs.len()),
+            false => Err("there is nothing to undo".to_string()),
+        }
+    }
+
+    /// Reapply the last undone filter pass. Returns the length of the
+    /// result set
+    pub fn redo(&mut self) -> Result<usize, String> {
+        let addrs = self.addrs.as_mut().ok_or("You have to select a type first")?;
+        match self.history.redo(addrs) {
+            true => Ok(addrs.len()),
+            false => Err("there is nothing to redo".to_string()),
+        }
+    }
+
+    pub fn select(&mut self, args: &SelectArgs) -> Result<&WatchEntry, String> {
+        let (Some(proc), Some(addrs), Some(val_type)) = (&self.process, &self.addrs, self.val_type)
+        else {
+            return Err("You have to select a type first".to_string());
+        };
+        let addr = addrs.get_addr(args.to_select)?;
+        self.watch_list.push(WatchEntry {
+            addr,
+            expr: AddressExpr::relative_to_module(&proc.modules(), addr),
+            val_type,
+            size: addrs.value_size(),
+            label: args.label.clone().unwrap_or_default(),
+            description: String::new(),
+        });
+        Ok(self.watch_list.last().unwrap())
+    }
+
+    pub fn unselect(&mut self, args: &UnselectArgs) -> Result<WatchEntry, String> {
+        if args.to_unselect < self.watch_list.len() {
+            Ok(self.watch_list.remove(args.to_unselect))
+        } else {
+            Err(format!(
+                "there is no selected address with index {}",
+                args.to_unselect
+            ))
+        }
+    }
+
+    fn get_selected(&self, idx: usize) -> Result<&WatchEntry, String> {
+        self.watch_list
+            .get(idx)
+            .ok_or(format!("there is no selected address with index {}", idx))
+    }
+
+    pub fn set(&mut self, args: &SetArgs) -> Result<(), String> {
+        let Some(proc) = &self.process else {
+            return Err("You have to select a process first".to_string());
+        };
+        let entry = self.get_selected(args.selected)?;
+        // Strings check themselves that they fit
+        if entry.val_type.size().is_none() && !entry.val_type.is_text() {
+            let len = entry.val_type.parse_bytes(&args.value)?.len();
+            if len != entry.size {
+                return Err(format!("value has to be {} bytes long", entry.size));
             }
-            ValType::U128 => {
-                self.addrs = Some(Box::new(AddrsSimple::<u128, MemoryReaderSimple>::new(proc)));
+        }
+        let mut memory_reader = MemoryReaderSimple::new(proc);
+        entry
+            .val_type
+            .write(&mut memory_reader, entry.addr, &args.value)
+    }
+
+    pub fn freeze(&mut self, args: &FreezeArgs) -> Result<FrozenEntry, String> {
+        let (Some(proc), Some(freezer)) = (&self.process, &self.freezer) else {
+            return Err("You have to select a process first".to_string());
+        };
+        let WatchEntry { addr, val_type, size, .. } = *self.get_selected(args.selected)?;
+
+        let value = match &args.value {
+            Some(value) if val_type.is_text() => val_type.fit_text(value, size)?,
+            Some(value) => {
+                let value = val_type.parse_bytes(value)?;
+                if value.len() != size {
+                    return Err(format!("value has to be {} bytes long", size));
+                }
+                value
             }
-            ValType::I64 => {
-                self.addrs = Some(Box::new(AddrsSimple::<i64, MemoryReaderSimple>::new(proc)));
+            None => {
+                let mut value = vec![0; size];
+                MemoryReaderSimple::new(proc)
+                    .read_bytes(addr, &mut value)
+                    .map_err(|e| e.to_string())?;
+                value
             }
-            ValType::U64 => {
-                self.addrs = Some(Box::new(AddrsSimple::<u64, MemoryReaderSimple>::new(proc)));
+        };
+        let entry = FrozenEntry {
+            addr,
+            val_type,
+            value,
+            mode: args.mode,
+        };
+        freezer.freeze(entry.clone());
+        Ok(entry)
+    }
+
+    pub fn unfreeze(&mut self, args: &UnfreezeArgs) -> Result<FrozenEntry, String> {
+        self.freezer
+            .as_ref()
+            .and_then(|freezer| freezer.unfreeze(args.frozen))
+            .ok_or(format!("there is no frozen address with index {}", args.frozen))
+    }
+
+    /// Address an expression like `libgame.so+1A2B0` stands for in
+    /// the process right now
+    pub fn resolve_addr(&self, expr: &str) -> Result<usize, String> {
+        let Some(proc) = &self.process else {
+            return Err("You have to select a process first".to_string());
+        };
+        let expr: AddressExpr = expr.parse()?;
+        expr.resolve(&proc.modules(), &mut MemoryReaderSimple::new(proc))
+    }
+
+    pub fn pointer_scan(
+        &self,
+        args: &PointerScanArgs,
+        report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> Result<Vec<PointerChain>, String> {
+        let target = self.resolve_addr(&args.addr)?;
+        let proc = self.process.as_ref().unwrap();
+        let saved = args
+            .intersect
+            .iter()
+            .map(|path| {
+                SavedPointerMap::load(path)
+                    .map_err(|e| format!("couldn't load {}: {}", path.display(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let threads = self.config.threads;
+        let map = match self.config.reader {
+            ReaderKind::Simple => {
+                PointerMap::build::<MemoryReaderSimple>(proc, threads, report_progress)
             }
-            ValType::I32 => {
-                self.addrs = Some(Box::new(AddrsSimple::<i32, MemoryReaderSimple>::new(proc)));
+            ReaderKind::Vm => PointerMap::build::<MemoryReaderVm>(proc, threads, report_progress),
+        };
+        if let Some(path) = &args.save {
+            map.save(path, target)
+                .map_err(|e| format!("couldn't save {}: {}", path.display(), e))?;
+        }
+        let keep = |chain: &PointerChain| saved.iter().all(|saved| saved.leads_to_target(chain));
+        Ok(map.find_chains(target, args.depth, args.max_offset, args.max_results, keep))
+    }
+
+    /// Save the selected addresses to a table, with the values of the
+    /// frozen ones. Returns how many were saved
+    pub fn table_save(&self, path: &Path) -> Result<usize, String> {
+        let frozen = match &self.freezer {
+            Some(freezer) => freezer.entries(),
+            None => Vec::new(),
+        };
+        let entries: Vec<_> = self
+            .watch_list
+            .iter()
+            .map(|entry| {
+                let frozen = frozen.iter().find(|frozen| frozen.addr == entry.addr);
+                TableEntry {
+                    label: entry.label.clone(),
+                    address: entry.expr.to_string(),
+                    val_type: entry.val_type,
+                    size: entry.val_type.size().is_none().then_some(entry.size),
+                    frozen: frozen.is_some(),
+                    value: frozen.map(|frozen| entry.val_type.format_value(&frozen.value)),
+                    freeze_mode: frozen.map(|frozen| frozen.mode),
+                    description: entry.description.clone(),
+                }
+            })
+            .collect();
+        let len = entries.len();
+        Table { entries }.save(path)?;
+        Ok(len)
+    }
+
+    /// Select the entries of a table and freeze the frozen ones.
+    /// Entries that can't be resolved in the process are skipped.
+    /// Returns how many got loaded and what went wrong with the rest
+    pub fn table_load(&mut self, path: &Path) -> Result<(usize, Vec<String>), String> {
+        let table = Table::load(path)?;
+        self.load_table(table)
+    }
+
+    /// Like `table_load`, for a Cheat Engine table. Entries it can't
+    /// translate are reported along with the ones that didn't load
+    pub fn table_import(&mut self, path: &Path) -> Result<(usize, Vec<String>), String> {
+        let xml = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        let (table, mut problems) = cheat_engine::import_ct(&xml)
+            .map_err(|e| format!("couldn't parse {}: {}", path.display(), e))?;
+        let (loaded, load_problems) = self.load_table(table)?;
+        problems.extend(load_problems);
+        Ok((loaded, problems))
+    }
+
+    fn load_table(&mut self, table: Table) -> Result<(usize, Vec<String>), String> {
+        let Some(proc) = &self.process else {
+            return Err("You have to select a process first".to_string());
+        };
+        let modules = proc.modules();
+        let mut loaded = 0;
+        let mut problems = Vec::new();
+        for entry in table.entries {
+            let name = match entry.label.is_empty() {
+                true => entry.address.clone(),
+                false => entry.label.clone(),
+            };
+            match self.load_table_entry(entry, &modules) {
+                Ok(()) => loaded += 1,
+                Err(err) => problems.push(format!("{}: {}", name, err)),
             }
-            ValType::U32 => {
-                self.addrs = Some(Box::new(AddrsSimple::<u32, MemoryReaderSimple>::new(proc)));
+        }
+        Ok((loaded, problems))
+    }
+
+    fn load_table_entry(&mut self, entry: TableEntry, modules: &[Module]) -> Result<(), String> {
+        let proc = self.process.as_ref().unwrap();
+        let expr: AddressExpr = entry.address.parse()?;
+        let addr = expr.resolve(modules, &mut MemoryReaderSimple::new(proc))?;
+        let size = entry
+            .val_type
+            .size()
+            .or(entry.size)
+            .ok_or(format!("{} entries need a size", entry.val_type.name()))?;
+        self.watch_list.push(WatchEntry {
+            addr,
+            expr,
+            val_type: entry.val_type,
+            size,
+            label: entry.label,
+            description: entry.description,
+        });
+        if entry.frozen {
+            let freeze_args = FreezeArgs {
+                selected: self.watch_list.len() - 1,
+                value: entry.value,
+                mode: entry.freeze_mode.unwrap_or(FreezeMode::Exact),
+            };
+            if let Err(err) = self.freeze(&freeze_args) {
+                self.watch_list.pop();
+                return Err(err);
             }
-            ValType::I16 => {
-                self.addrs = Some(Box::new(AddrsSimple::<i16, MemoryReaderSimple>::new(proc)));
+        }
+        Ok(())
+    }
+
+    pub fn config(&mut self, args: &ConfigArgs) {
+        match args.setting {
+            ConfigSetting::FreezeInterval { millis } => {
+                self.config.freeze_interval = Duration::from_millis(millis);
+                if let Some(freezer) = &self.freezer {
+                    freezer.set_interval(self.config.freeze_interval);
+                }
             }
-            ValType::U16 => {
-                self.addrs = Some(Box::new(AddrsSimple::<u16, MemoryReaderSimple>::new(proc)));
+            ConfigSetting::DiskThreshold { mib } => {
+                self.config.disk_threshold = mib * 1024 * 1024;
             }
-            ValType::I8 => {
-                self.addrs = Some(Box::new(AddrsSimple::<i8, MemoryReaderSimple>::new(proc)));
+            ConfigSetting::SpillDir { ref path } => {
+                self.config.spill_dir = path.clone();
             }
-            ValType::U8 => {
-                self.addrs = Some(Box::new(AddrsSimple::<u8, MemoryReaderSimple>::new(proc)));
+            ConfigSetting::History { len } => {
+                self.config.history_len = len;
+            }
+            ConfigSetting::Storage { kind } => {
+                self.config.storage = kind;
+            }
+            ConfigSetting::Reader { kind } => {
+                self.config.reader = kind;
+            }
+            ConfigSetting::Alignment { alignment } => {
+                self.config.alignment = alignment;
+            }
+            ConfigSetting::Threads { count } => {
+                self.config.threads = count;
             }
         }
     }
@@ -79,3 +530,154 @@ impl Context {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addresses::{Baseline, FloatCmp};
+    use std::process;
+
+    #[test]
+    fn watch_list_survives_type_change() {
+        let mut ctx = Context::new();
+        ctx.process(&ProcessArgs { pid: process::id() }).unwrap();
+        ctx.change_type(&TypeArgs { val_type: ValType::U16 });
+        let value = Box::new(0xbeefu16);
+        let addr = value.as_ref() as *const u16 as usize;
+        let scan_expr = ScanExpr::Equal(value.to_string(), FloatCmp::default());
+        let mut addrs = ctx.addrs.take().unwrap();
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        let idx = addrs.get_addrs().unwrap().iter().position(|&a| a == addr).unwrap();
+        ctx.addrs = Some(addrs);
+
+        let label = Some("beef".to_string());
+        ctx.select(&SelectArgs { to_select: idx, label }).unwrap();
+        ctx.change_type(&TypeArgs { val_type: ValType::I64 });
+        ctx.set(&SetArgs { selected: 0, value: "7".to_string() }).unwrap();
+
+        assert_eq!(ctx.watch_list[0].val_type, ValType::U16);
+        assert_eq!(ctx.watch_list[0].label, "beef");
+        let mut buf = [0; 2];
+        MemoryReaderSimple::new(ctx.process.as_ref().unwrap())
+            .read_bytes(addr, &mut buf)
+            .unwrap();
+        assert_eq!(u16::from_le_bytes(buf), 7);
+        assert!(ctx.set(&SetArgs { selected: 0, value: "-1".to_string() }).is_err());
+        assert!(ctx.unselect(&UnselectArgs { to_unselect: 1 }).is_err());
+    }
+
+    #[test]
+    fn big_initial_scans_go_to_disk() {
+        let mut ctx = Context::new();
+        ctx.process(&ProcessArgs { pid: process::id() }).unwrap();
+        let proc = ctx.process.as_ref().unwrap();
+        let not_zero = ScanExpr::NotEqual("0".to_string(), FloatCmp::default());
+        let simple = estimate_results_size::<u32>(proc, &ctx.config, &not_zero);
+        assert!(estimate_results_size::<u32>(proc, &ctx.config, &ScanExpr::Unknown) > simple);
+        ctx.config.storage = StorageKind::Compact;
+        let compact = estimate_results_size::<u32>(proc, &ctx.config, &not_zero);
+        assert!(compact < simple);
+
+        ctx.config.storage = StorageKind::Simple;
+        ctx.config.disk_threshold = compact;
+        ctx.change_type(&TypeArgs { val_type: ValType::U32 });
+        ctx.prepare_scan(&not_zero);
+        assert!(ctx.get_type().contains("disk"));
+        ctx.config.storage = StorageKind::Compact;
+        ctx.prepare_scan(&not_zero);
+        assert!(!ctx.get_type().contains("disk"));
+
+        // Filters only keep fewer results, so they stay where they are
+        ctx.config.disk_threshold = usize::MAX;
+        ctx.change_type(&TypeArgs { val_type: ValType::U32 });
+        let scan_expr = ScanExpr::Equal("12345".to_string(), FloatCmp::default());
+        ctx.prepare_scan(&scan_expr);
+        let mut addrs = ctx.addrs.take().unwrap();
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        ctx.addrs = Some(addrs);
+        ctx.config.disk_threshold = 0;
+        ctx.prepare_scan(&ScanExpr::Changed);
+        assert!(!ctx.get_type().contains("disk"));
+        assert_eq!(ctx.addrs.as_ref().unwrap().passes(), 1);
+    }
+
+    #[test]
+    fn table_load_and_save() {
+        let value = Box::new(1234i32);
+        let addr = value.as_ref() as *const i32 as usize;
+        let mut ctx = Context::new();
+        ctx.process(&ProcessArgs { pid: process::id() }).unwrap();
+        let path = std::env::temp_dir().join(format!("memori-table-{}.toml", process::id()));
+        let table = format!(
+            "[[entry]]\nlabel = \"score\"\naddress = \"{:x}\"\ntype = \"i32\"\n\
+             frozen = true\nvalue = \"77\"\n\n\
+             [[entry]]\naddress = \"libmissing.so+10\"\ntype = \"u8\"\n\n\
+             [[entry]]\nlabel = \"name\"\naddress = \"{:x}\"\ntype = \"utf8\"\n",
+            addr, addr
+        );
+        std::fs::write(&path, table).unwrap();
+        let (loaded, problems) = ctx.table_load(&path).unwrap();
+        assert_eq!(loaded, 1);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(ctx.watch_list[0].addr, addr);
+        assert_eq!(ctx.freezer.as_ref().unwrap().entries()[0].value, 77i32.to_le_bytes());
+
+        assert_eq!(ctx.table_save(&path), Ok(1));
+        let table = Table::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(table.entries[0].label, "score");
+        assert_eq!(ctx.resolve_addr(&table.entries[0].address), Ok(addr));
+        assert_eq!(table.entries[0].value.as_deref(), Some("77"));
+        assert_eq!(table.entries[0].freeze_mode, Some(FreezeMode::Exact));
+    }
+
+    #[test]
+    fn table_round_trips_frozen_strings() {
+        let name = Box::new(*b"Bobby\0\0\0\0\0\0\0\0\0\0\0");
+        let wide: Box<[u16; 8]> = Box::new([b'E' as u16, b'v' as u16, b'e' as u16, 0, 0, 0, 0, 0]);
+        let (addr, wide_addr) = (name.as_ptr() as usize, wide.as_ptr() as usize);
+        let mut ctx = Context::new();
+        ctx.process(&ProcessArgs { pid: process::id() }).unwrap();
+        let path = std::env::temp_dir().join(format!("memori-strings-{}.toml", process::id()));
+        let table = format!(
+            "[[entry]]\naddress = \"{:x}\"\ntype = \"utf8\"\nsize = 16\n\
+             frozen = true\nvalue = \"Bob\"\n\n\
+             [[entry]]\naddress = \"{:x}\"\ntype = \"utf16\"\nsize = 16\n\
+             frozen = true\nvalue = \"Al\"\n",
+            addr, wide_addr
+        );
+        std::fs::write(&path, table).unwrap();
+        assert_eq!(ctx.table_load(&path), Ok((2, Vec::new())));
+        let frozen = ctx.freezer.as_ref().unwrap().entries();
+        assert_eq!(frozen[0].value, b"Bob\0");
+        assert_eq!(frozen[1].value, [b'A', 0, b'l', 0, 0, 0]);
+
+        ctx.table_save(&path).unwrap();
+        let saved = Table::load(&path).unwrap();
+        assert_eq!(saved.entries[0].value.as_deref(), Some("Bob"));
+        assert_eq!(saved.entries[1].value.as_deref(), Some("Al"));
+        assert_eq!(saved.entries[1].size, Some(16));
+        let mut reloaded = Context::new();
+        reloaded.process(&ProcessArgs { pid: process::id() }).unwrap();
+        assert_eq!(reloaded.table_load(&path), Ok((2, Vec::new())));
+
+        // Cheat Engine counts the length of strings in characters
+        let ct = format!(
+            "<CheatTable><CheatEntries><CheatEntry><Description>\"Name\"</Description>\
+             <VariableType>String</VariableType><Length>16</Length>\
+             <Address>{:x}</Address><LastState Value=\"Bob\" Activated=\"1\"/>\
+             </CheatEntry></CheatEntries></CheatTable>",
+            addr
+        );
+        std::fs::write(&path, ct).unwrap();
+        let mut imported = Context::new();
+        imported.process(&ProcessArgs { pid: process::id() }).unwrap();
+        assert_eq!(imported.table_import(&path), Ok((1, Vec::new())));
+        std::fs::remove_file(&path).unwrap();
+        std::hint::black_box((name, wide));
+    }
+}
@@ -0,0 +1,213 @@
+use clap::ValueEnum;
+
+use crate::addresses::{Bounds, FloatCmp, ScanExpr};
+use crate::commands::FilterOperator;
+
+/// Parser for the expressions accepted by `filter`, e.g.
+/// `> 10 and < 500 and changed` or `not (== 100 or == 200)`.
+///
+/// ```text
+/// expr  := and ("or" and)*
+/// and   := unary ("and" unary)*
+/// unary := "not" unary | "(" expr ")" | operator operand*
+/// ```
+///
+/// Options like the float comparison mode apply to every operator in
+/// the expression that uses them.
+pub struct FilterParser {
+    tokens: Vec<String>,
+    pos: usize,
+    float_cmp: FloatCmp,
+    bounds: Bounds,
+}
+
+impl FilterParser {
+    pub fn new(words: &[String], float_cmp: FloatCmp, bounds: Bounds) -> FilterParser {
+        // Parentheses don't have to be separated by spaces
+        let mut tokens = Vec::new();
+        for word in words {
+            let mut rest = word.as_str();
+            while !rest.is_empty() {
+                let end = rest.find(['(', ')']).unwrap_or(rest.len());
+                let end = if end == 0 { 1 } else { end };
+                tokens.push(rest[..end].to_string());
+                rest = &rest[end..];
+            }
+        }
+        FilterParser {
+            tokens,
+            pos: 0,
+            float_cmp,
+            bounds,
+        }
+    }
+
+    pub fn parse(mut self) -> Result<ScanExpr, String> {
+        let expr = self.parse_or()?;
+        match self.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.as_str())
+    }
+
+    fn next(&mut self, expected: &str) -> Result<String, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(format!("expected {} at the end of expression", expected))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn parse_or(&mut self) -> Result<ScanExpr, String> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some("or") {
+            self.pos += 1;
+            expr = ScanExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<ScanExpr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some("and") {
+            self.pos += 1;
+            expr = ScanExpr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<ScanExpr, String> {
+        match self.peek() {
+            Some("not") => {
+                self.pos += 1;
+                Ok(ScanExpr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some("(") => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                match self.next("\")\"")?.as_str() {
+                    ")" => Ok(expr),
+                    token => Err(format!("expected \")\", found {:?}", token)),
+                }
+            }
+            _ => self.parse_operator(),
+        }
+    }
+
+    fn parse_operator(&mut self) -> Result<ScanExpr, String> {
+        let token = self.next("an operator")?;
+        let operator = FilterOperator::from_str(&token, true)
+            .map_err(|_| format!("unknown operator {:?}", token))?;
+        let float_cmp = self.float_cmp;
+        let bounds = self.bounds;
+        let expr = match operator {
+            FilterOperator::Less => ScanExpr::Less(self.next("an operand")?),
+            FilterOperator::LessEqual => ScanExpr::LessEqual(self.next("an operand")?),
+            FilterOperator::Greater => ScanExpr::Greater(self.next("an operand")?),
+            FilterOperator::GreaterEqual => ScanExpr::GreaterEqual(self.next("an operand")?),
+            FilterOperator::Equal => ScanExpr::Equal(self.next("an operand")?, float_cmp),
+            FilterOperator::NotEqual => ScanExpr::NotEqual(self.next("an operand")?, float_cmp),
+            FilterOperator::Between => {
+                ScanExpr::Between(self.next("a lower bound")?, self.next("an upper bound")?, bounds)
+            }
+            FilterOperator::NotBetween => ScanExpr::NotBetween(
+                self.next("a lower bound")?,
+                self.next("an upper bound")?,
+                bounds,
+            ),
+            FilterOperator::Changed => ScanExpr::Changed,
+            FilterOperator::NotChanged => ScanExpr::NotChanged,
+            FilterOperator::Increased => ScanExpr::Increased,
+            FilterOperator::Decreased => ScanExpr::Decreased,
+            FilterOperator::IncreasedBy => {
+                ScanExpr::IncreasedBy(self.next("an operand")?, float_cmp)
+            }
+            FilterOperator::DecreasedBy => {
+                ScanExpr::DecreasedBy(self.next("an operand")?, float_cmp)
+            }
+            FilterOperator::IncreasedByPercent => {
+                ScanExpr::IncreasedByPercent(self.next("an operand")?)
+            }
+            FilterOperator::DecreasedByPercent => {
+                ScanExpr::DecreasedByPercent(self.next("an operand")?)
+            }
+            FilterOperator::Unknown => ScanExpr::Unknown,
+        };
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(expr: &str) -> Result<ScanExpr, String> {
+        let words: Vec<_> = expr.split_whitespace().map(String::from).collect();
+        FilterParser::new(&words, FloatCmp::Exact, Bounds::Inclusive).parse()
+    }
+
+    #[test]
+    fn filter_parser_precedence() {
+        let expr = parse("> 10 and < 500 or not changed and == -3").unwrap();
+        assert_eq!(
+            format!("{:?}", expr),
+            format!(
+                "{:?}",
+                ScanExpr::Or(
+                    Box::new(ScanExpr::And(
+                        Box::new(ScanExpr::Greater("10".to_string())),
+                        Box::new(ScanExpr::Less("500".to_string())),
+                    )),
+                    Box::new(ScanExpr::And(
+                        Box::new(ScanExpr::Not(Box::new(ScanExpr::Changed))),
+                        Box::new(ScanExpr::Equal("-3".to_string(), FloatCmp::Exact)),
+                    )),
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn filter_parser_parentheses() {
+        let expr = parse("not(== 1 or between 2 5)").unwrap();
+        assert_eq!(
+            format!("{:?}", expr),
+            format!(
+                "{:?}",
+                ScanExpr::Not(Box::new(ScanExpr::Or(
+                    Box::new(ScanExpr::Equal("1".to_string(), FloatCmp::Exact)),
+                    Box::new(ScanExpr::Between(
+                        "2".to_string(),
+                        "5".to_string(),
+                        Bounds::Inclusive
+                    )),
+                )))
+            )
+        );
+    }
+
+    #[test]
+    fn filter_parser_errors() {
+        assert!(parse("==").is_err());
+        assert!(parse("== 1 and").is_err());
+        assert!(parse("(== 1").is_err());
+        assert!(parse("== 1 )").is_err());
+        assert!(parse("=== 1").is_err());
+        assert!(parse("changed 1").is_err());
+    }
+
+    #[test]
+    fn compound_expr_eval() {
+        let expr = parse("> 10 and < 500 and changed or == 7").unwrap();
+        let vals = [(11u32, 11), (11, 12), (500, 1), (7, 7), (499, 0)];
+        let mut found = Vec::new();
+        expr.eval_expr(&mut |val, _| found.push(val), vals.into_iter(), 0..vals.len());
+        assert_eq!(found, vec![11, 7, 499]);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::commands::{FreezeMode, ValType};
+use crate::memory_reader::MemoryReader;
+use crate::process::Process;
+
+use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicBool, AtomicU64};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct FrozenEntry {
+    pub addr: usize,
+    pub val_type: ValType,
+    /// Value in little endian, as long as the selected value
+    pub value: Vec<u8>,
+    pub mode: FreezeMode,
+}
+
+/// Owns the frozen entries and a background thread that keeps
+/// writing them into the process' memory every interval. The thread
+/// is stopped when the freezer is dropped.
+pub struct Freezer {
+    entries: Arc<Mutex<Vec<FrozenEntry>>>,
+    interval_ms: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Freezer {
+    pub fn new<U: MemoryReader + 'static>(process: &Process, interval: Duration) -> Freezer {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let interval_ms = Arc::new(AtomicU64::new(interval.as_millis() as u64));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut memory_reader = U::new(process);
+        let thread = {
+            let entries = Arc::clone(&entries);
+            let interval_ms = Arc::clone(&interval_ms);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(atomic::Ordering::Relaxed) {
+                    Self::write_entries(&mut memory_reader, &mut entries.lock().unwrap());
+                    let interval = interval_ms.load(atomic::Ordering::Relaxed);
+                    thread::park_timeout(Duration::from_millis(interval));
+                }
+            })
+        };
+
+        Freezer {
+            entries,
+            interval_ms,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn write_entries<U: MemoryReader>(memory_reader: &mut U, entries: &mut [FrozenEntry]) {
+        let mut current = Vec::new();
+        for entry in entries.iter_mut() {
+            let allowed = match entry.mode {
+                FreezeMode::Exact => None,
+                FreezeMode::Increase => Some(Ordering::Greater),
+                FreezeMode::Decrease => Some(Ordering::Less),
+            };
+            if let Some(allowed) = allowed {
+                current.resize(entry.value.len(), 0);
+                if memory_reader.read_bytes(entry.addr, &mut current).is_ok()
+                    && entry.val_type.cmp_bytes(&current, &entry.value) == Some(allowed)
+                {
+                    entry.value.copy_from_slice(&current);
+                    continue;
+                }
+            }
+            // Errors are ignored on purpose. The address might have
+            // been unmapped, in which case there's nothing to be done
+            // besides the user unfreezing it.
+            let _ = memory_reader.write_bytes(entry.addr, &entry.value);
+        }
+    }
+
+    /// Start freezing an entry. An entry already frozen at the same
+    /// address gets replaced.
+    pub fn freeze(&self, entry: FrozenEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.iter_mut().find(|e| e.addr == entry.addr) {
+            Some(old) => *old = entry,
+            None => entries.push(entry),
+        }
+        drop(entries);
+        self.wake();
+    }
+
+    pub fn unfreeze(&self, idx: usize) -> Option<FrozenEntry> {
+        let mut entries = self.entries.lock().unwrap();
+        if idx < entries.len() {
+            Some(entries.remove(idx))
+        } else {
+            None
+        }
+    }
+
+    pub fn entries(&self) -> Vec<FrozenEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval_ms
+            .store(interval.as_millis() as u64, atomic::Ordering::Relaxed);
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+impl Drop for Freezer {
+    fn drop(&mut self) {
+        self.stop.store(true, atomic::Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_reader::MemoryReaderSimple;
+    use std::process;
+    use std::ptr;
+    use std::time::Instant;
+
+    fn read_volatile(val: &i32) -> i32 {
+        unsafe { ptr::read_volatile(val) }
+    }
+
+    /// Poll until f is true, failing if the freezer takes too long
+    fn wait_for(mut f: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !f() {
+            assert!(Instant::now() < deadline, "freezer didn't catch up");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn freezer_rewrites_and_allows_increase() {
+        let self_proc = Process::try_new(process::id()).unwrap();
+        let freezer = Freezer::new::<MemoryReaderSimple>(&self_proc, Duration::from_millis(10));
+        let mut mem_reader = MemoryReaderSimple::new(&self_proc);
+        let exact = Box::new(5i32);
+        let increase = Box::new(5i32);
+        let exact_addr = exact.as_ref() as *const i32 as usize;
+        let increase_addr = increase.as_ref() as *const i32 as usize;
+
+        freezer.freeze(FrozenEntry {
+            addr: exact_addr,
+            val_type: ValType::I32,
+            value: 100i32.to_le_bytes().to_vec(),
+            mode: FreezeMode::Exact,
+        });
+        freezer.freeze(FrozenEntry {
+            addr: increase_addr,
+            val_type: ValType::I32,
+            value: 5i32.to_le_bytes().to_vec(),
+            mode: FreezeMode::Increase,
+        });
+        wait_for(|| read_volatile(&exact) == 100);
+
+        mem_reader.write_bytes(increase_addr, &10i32.to_le_bytes()).unwrap();
+        wait_for(|| freezer.entries()[1].value == 10i32.to_le_bytes());
+        assert_eq!(read_volatile(&increase), 10);
+
+        mem_reader.write_bytes(increase_addr, &1i32.to_le_bytes()).unwrap();
+        wait_for(|| read_volatile(&increase) == 10);
+
+        assert_eq!(freezer.unfreeze(0).unwrap().addr, exact_addr);
+        assert_eq!(freezer.entries().len(), 1);
+        assert!(freezer.unfreeze(1).is_none());
+    }
+}
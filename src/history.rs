@@ -0,0 +1,151 @@
+use std::mem;
+
+use crate::addresses::Addresses;
+
+/// A result set together with what produced it
+struct HistoryEntry {
+    expr: String,
+    addrs: Box<dyn Addresses>,
+}
+
+/// Result sets from before and after the current one, so a mistaken
+/// filter can be undone. The current result set itself lives in
+/// `Context::addrs`, the history only keeps what's been replaced.
+pub struct History {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    /// What produced the current result set
+    current: String,
+}
+
+impl History {
+    pub fn new(current: String) -> History {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            current,
+        }
+    }
+
+    /// Remember the result set from before a pass described by expr.
+    /// Only the last `max_len` result sets are kept.
+    pub fn record(&mut self, before: Box<dyn Addresses>, expr: String, max_len: usize) {
+        self.redo.clear();
+        let before_expr = mem::replace(&mut self.current, expr);
+        self.undo.push(HistoryEntry {
+            expr: before_expr,
+            addrs: before,
+        });
+        if self.undo.len() > max_len {
+            self.undo.drain(..self.undo.len() - max_len);
+        }
+    }
+
+    /// Swap addrs with the result set from before the last pass.
+    /// Returns false if there's nothing to undo.
+    pub fn undo(&mut self, addrs: &mut Box<dyn Addresses>) -> bool {
+        Self::step(&mut self.undo, &mut self.redo, &mut self.current, addrs)
+    }
+
+    /// Swap addrs with the result set from the last undone pass.
+    /// Returns false if there's nothing to redo.
+    pub fn redo(&mut self, addrs: &mut Box<dyn Addresses>) -> bool {
+        Self::step(&mut self.redo, &mut self.undo, &mut self.current, addrs)
+    }
+
+    fn step(
+        from: &mut Vec<HistoryEntry>,
+        to: &mut Vec<HistoryEntry>,
+        current: &mut String,
+        addrs: &mut Box<dyn Addresses>,
+    ) -> bool {
+        let Some(mut entry) = from.pop() else {
+            return false;
+        };
+        mem::swap(&mut entry.addrs, addrs);
+        mem::swap(&mut entry.expr, current);
+        to.push(entry);
+        true
+    }
+
+    /// Expression and hit count of every result set, oldest first,
+    /// and the index of the current one. `current_len` is the length
+    /// of the current result set.
+    pub fn entries(&self, current_len: usize) -> (Vec<(&str, usize)>, usize) {
+        let undo = self
+            .undo
+            .iter()
+            .map(|entry| (entry.expr.as_str(), entry.addrs.len()));
+        let redo = self
+            .redo
+            .iter()
+            .rev()
+            .map(|entry| (entry.expr.as_str(), entry.addrs.len()));
+        let entries = undo
+            .chain([(self.current.as_str(), current_len)])
+            .chain(redo)
+            .collect();
+        (entries, self.undo.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addresses::{AddrsSimple, Baseline, FloatCmp, ScanExpr};
+    use crate::context::Context;
+    use crate::memory_reader::MemoryReaderVm;
+    use crate::process::Process;
+    use std::process;
+
+    #[test]
+    fn history_undo_redo() {
+        let mut ctx = Context::new();
+        ctx.process = Some(Process::try_new(process::id()).unwrap());
+        let value = Box::new(0x4157_0e1d_u32);
+        let addr = value.as_ref() as *const u32 as usize;
+        let process = ctx.process.as_ref().unwrap();
+        let mut addrs: Box<dyn Addresses> =
+            Box::new(AddrsSimple::<u32, MemoryReaderVm>::new(process));
+        let mut history = History::new("type u32".to_string());
+
+        let scan_expr = ScanExpr::Equal(value.to_string(), FloatCmp::default());
+        let before = addrs.try_clone_box().unwrap();
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        history.record(before, "== 1096224285".to_string(), 10);
+        let found = addrs.len();
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+
+        // Oops
+        let scan_expr = ScanExpr::Changed;
+        let before = addrs.try_clone_box().unwrap();
+        addrs
+            .scan(&ctx, &scan_expr, &Baseline::Previous, Box::new(|_, _| ()))
+            .unwrap();
+        history.record(before, "changed".to_string(), 10);
+        assert!(!addrs.get_addrs().unwrap().contains(&addr));
+
+        assert!(history.undo(&mut addrs));
+        assert_eq!(addrs.len(), found);
+        assert!(addrs.get_addrs().unwrap().contains(&addr));
+        let (entries, current) = history.entries(addrs.len());
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[current], ("== 1096224285", found));
+        assert_eq!(entries[2].0, "changed");
+
+        assert!(history.undo(&mut addrs));
+        assert!(addrs.is_empty());
+        assert!(!history.undo(&mut addrs));
+        assert!(history.redo(&mut addrs));
+        assert!(history.redo(&mut addrs));
+        assert!(!history.redo(&mut addrs));
+        assert!(!addrs.get_addrs().unwrap().contains(&addr));
+
+        history.record(addrs.try_clone_box().unwrap(), "unknown".to_string(), 1);
+        let (entries, current) = history.entries(addrs.len());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[current].0, "unknown");
+    }
+}
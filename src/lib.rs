@@ -1,11 +1,24 @@
 #![feature(generic_const_exprs)]
+#![allow(incomplete_features)]
 
+pub mod address_expr;
 pub mod addresses;
+pub mod addresses_bytes;
+pub mod addresses_compact;
+pub mod addresses_disk;
 pub mod animations;
+pub mod cheat_engine;
 pub mod commands;
 pub mod context;
+pub mod filter_parser;
+pub mod freezer;
+pub mod history;
 pub mod memory_map;
 pub mod memory_reader;
+pub mod pattern;
+pub mod pointer_scan;
 pub mod process;
 pub mod repl;
+pub mod table;
 pub mod util;
+pub mod val_type;
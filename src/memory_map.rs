@@ -1,4 +1,7 @@
 use std::cmp::{Eq, PartialEq};
+use std::error;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct MemoryMap {
@@ -9,6 +12,9 @@ pub struct MemoryMap {
     pub dev: Device,
     pub inode: usize,
     pub pathname: String,
+    /// The file backing the mapping was deleted. The ` (deleted)`
+    /// suffix is stripped from the pathname
+    pub deleted: bool,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -26,72 +32,134 @@ pub struct Device {
     pub minor: i32,
 }
 
-impl MemoryMap {
-    pub fn from(line: &str) -> MemoryMap {
+/// Why a line from /proc/pid/maps couldn't be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub enum MemoryMapParseError {
+    MissingColumn(&'static str),
+    BadAddress(String),
+    BadPermissions(String),
+    BadOffset(String),
+    BadDevice(String),
+    BadInode(String),
+}
+
+impl fmt::Display for MemoryMapParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingColumn(column) => write!(f, "missing {} column", column),
+            Self::BadAddress(address) => write!(f, "bad address range {:?}", address),
+            Self::BadPermissions(perms) => write!(f, "bad permissions {:?}", perms),
+            Self::BadOffset(offset) => write!(f, "bad offset {:?}", offset),
+            Self::BadDevice(dev) => write!(f, "bad device {:?}", dev),
+            Self::BadInode(inode) => write!(f, "bad inode {:?}", inode),
+        }
+    }
+}
+
+impl error::Error for MemoryMapParseError {}
+
+const DELETED_SUFFIX: &str = " (deleted)";
+
+impl FromStr for MemoryMap {
+    type Err = MemoryMapParseError;
+
+    fn from_str(line: &str) -> Result<MemoryMap, MemoryMapParseError> {
         // example input from `man proc_pid_maps`:
         // address           perms offset  dev   inode       pathname
         // 00400000-00452000 r-xp 00000000 08:02 173521      /usr/bin/dbus-daemon
-        let cols: Vec<_> = line.split_whitespace().collect();
+        let mut rest = line;
+        let mut next_col = |name| {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let (col, after) = rest.split_at(end);
+            rest = after;
+            if col.is_empty() {
+                Err(MemoryMapParseError::MissingColumn(name))
+            } else {
+                Ok(col)
+            }
+        };
+        let address = next_col("address")?;
+        let perms = next_col("perms")?;
+        let offset = next_col("offset")?;
+        let dev = next_col("dev")?;
+        let inode = next_col("inode")?;
 
-        if cols.len() < 5 {
-            panic!("incorrect line supplied for MemoryMap");
-        }
+        let bad_address = || MemoryMapParseError::BadAddress(address.to_string());
+        let (addr_start, addr_end) = address.split_once('-').ok_or_else(bad_address)?;
+        let addr_start = usize::from_str_radix(addr_start, 16).map_err(|_| bad_address())?;
+        let addr_end = usize::from_str_radix(addr_end, 16).map_err(|_| bad_address())?;
+        let perms = perms.parse()?;
+        let offset = usize::from_str_radix(offset, 16)
+            .map_err(|_| MemoryMapParseError::BadOffset(offset.to_string()))?;
+        let dev = dev.parse()?;
+        let inode = inode
+            .parse()
+            .map_err(|_| MemoryMapParseError::BadInode(inode.to_string()))?;
 
-        let (addr_start, addr_end) = cols[0]
-            .split_once("-")
-            .expect("incorrect input in memory maps");
-        let addr_start =
-            usize::from_str_radix(addr_start, 16).expect("address is not a hex number");
-        let addr_end = usize::from_str_radix(addr_end, 16).expect("address is not a hex number");
-        let perms = Permissions::from(cols[1]);
-        let offset = usize::from_str_radix(cols[2], 16).expect("offset is not a hex number");
-        let dev = Device::from(cols[3]);
-        let inode = cols[4].parse().expect("inode is not a number");
-        let pathname = cols.get(5).unwrap_or(&"").to_string();
-
-        MemoryMap {
+        // Whatever is left is the pathname, which can contain spaces
+        let pathname = rest.trim_start();
+        let (pathname, deleted) = match pathname.strip_suffix(DELETED_SUFFIX) {
+            Some(pathname) => (pathname, true),
+            None => (pathname, false),
+        };
+
+        Ok(MemoryMap {
             addr_start,
             addr_end,
             perms,
             offset,
             dev,
             inode,
-            pathname,
-        }
+            pathname: pathname.to_string(),
+            deleted,
+        })
     }
 }
 
-impl Permissions {
-    fn from(perms: &str) -> Permissions {
-        if perms.len() != 4 {
-            panic!("incorrect permission string supplied");
-        }
-        Permissions {
-            read: perms.as_bytes()[0] == b'r',
-            write: perms.as_bytes()[1] == b'w',
-            execute: perms.as_bytes()[2] == b'x',
-            private: perms.as_bytes()[3] == b'p',
-            shared: perms.as_bytes()[3] == b's',
+impl FromStr for Permissions {
+    type Err = MemoryMapParseError;
+
+    fn from_str(perms: &str) -> Result<Permissions, MemoryMapParseError> {
+        let bytes = perms.as_bytes();
+        let valid = bytes.len() == 4
+            && matches!(bytes[0], b'r' | b'-')
+            && matches!(bytes[1], b'w' | b'-')
+            && matches!(bytes[2], b'x' | b'-')
+            && matches!(bytes[3], b'p' | b's');
+        if !valid {
+            return Err(MemoryMapParseError::BadPermissions(perms.to_string()));
         }
+        Ok(Permissions {
+            read: bytes[0] == b'r',
+            write: bytes[1] == b'w',
+            execute: bytes[2] == b'x',
+            private: bytes[3] == b'p',
+            shared: bytes[3] == b's',
+        })
     }
 }
 
-impl Device {
-    fn from(dev: &str) -> Device {
-        let (major, minor) = dev.split_once(":").expect("bad device string supplied");
-        let major = i32::from_str_radix(major, 16).expect("major is not a hex number");
-        let minor = i32::from_str_radix(minor, 16).expect("minor is not a hex number");
-        Device { major, minor }
+impl FromStr for Device {
+    type Err = MemoryMapParseError;
+
+    fn from_str(dev: &str) -> Result<Device, MemoryMapParseError> {
+        let bad_device = || MemoryMapParseError::BadDevice(dev.to_string());
+        let (major, minor) = dev.split_once(':').ok_or_else(bad_device)?;
+        let major = i32::from_str_radix(major, 16).map_err(|_| bad_device())?;
+        let minor = i32::from_str_radix(minor, 16).map_err(|_| bad_device())?;
+        Ok(Device { major, minor })
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn memory_map_from_test() {
         let line = "00400000-00452000 r-xp 00000000 08:02 173521      /usr/bin/dbus-daemon";
-        let mm = MemoryMap::from(&line);
+        let mm: MemoryMap = line.parse().unwrap();
         assert_eq!(
             mm,
             MemoryMap {
@@ -111,7 +179,55 @@ mod tests {
                 },
                 inode: 173521,
                 pathname: "/usr/bin/dbus-daemon".to_string(),
+                deleted: false,
             }
         )
     }
+
+    #[test]
+    fn memory_map_pathname_test() {
+        let line = "7f2c0000-7f2c1000 rw-s 00001000 00:05 1337   /dev/shm/my game (deleted)";
+        let mm: MemoryMap = line.parse().unwrap();
+        assert_eq!(mm.pathname, "/dev/shm/my game");
+        assert!(mm.deleted);
+        assert!(mm.perms.shared);
+
+        let line = "7ffd0000-7ffd1000 rw-p 00000000 00:00 0                          [stack]";
+        let mm: MemoryMap = line.parse().unwrap();
+        assert_eq!(mm.pathname, "[stack]");
+        assert!(!mm.deleted);
+
+        let line = "7ffd0000-7ffd1000 rw-p 00000000 00:00 0";
+        let mm: MemoryMap = line.parse().unwrap();
+        assert_eq!(mm.pathname, "");
+    }
+
+    #[test]
+    fn memory_map_parse_error_test() {
+        let parse = |line: &str| line.parse::<MemoryMap>().unwrap_err();
+        assert_eq!(
+            parse("00400000-00452000 r-xp 00000000 08:02"),
+            MemoryMapParseError::MissingColumn("inode")
+        );
+        assert_eq!(
+            parse("00400000 r-xp 00000000 08:02 1"),
+            MemoryMapParseError::BadAddress("00400000".to_string())
+        );
+        assert_eq!(
+            parse("00400000-00452000 r-x 00000000 08:02 1"),
+            MemoryMapParseError::BadPermissions("r-x".to_string())
+        );
+        assert_eq!(
+            parse("00400000-00452000 r-xp 0000000g 08:02 1"),
+            MemoryMapParseError::BadOffset("0000000g".to_string())
+        );
+        assert_eq!(
+            parse("00400000-00452000 r-xp 00000000 0802 1"),
+            MemoryMapParseError::BadDevice("0802".to_string())
+        );
+        assert_eq!(
+            parse("00400000-00452000 r-xp 00000000 08:02 -1"),
+            MemoryMapParseError::BadInode("-1".to_string())
+        );
+    }
 }
@@ -1,17 +1,80 @@
 use crate::process::Process;
 
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::mem;
 use std::path::PathBuf;
 use std::fs::OpenOptions;
+use std::ptr;
 
-pub trait MemoryReader: Clone {
+/// How much memory is read at once by `MemoryReader::read_region`
+pub const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+/// Smallest unit that can fail to be read on its own. Chunks always
+/// start on a page boundary, as long as the region does.
+pub const PAGE_SIZE: usize = 4096;
+
+pub trait MemoryReader: Clone + Send + Sync {
     fn new(process: &Process) -> Self;
     fn read<T: Copy + FromLeBytes>(&mut self, addr: usize) -> T
     where
         [(); mem::size_of::<T>()]:;
-    fn write(&mut self, addr: usize, value: i32);
+    fn read_bytes(&mut self, addr: usize, buf: &mut [u8]) -> io::Result<()>;
+    /// Like read, but returns None instead of garbage when the memory
+    /// can't be read
+    fn try_read<T: Copy + FromLeBytes>(&mut self, addr: usize) -> Option<T>
+    where
+        [(); mem::size_of::<T>()]:,
+    {
+        let mut buffer = [0u8; mem::size_of::<T>()];
+        self.read_bytes(addr, &mut buffer).ok()?;
+        Some(T::from_le_bytes(&buffer))
+    }
+    fn write<T: ToLeBytes>(&mut self, addr: usize, value: T) -> io::Result<()>;
+    fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> io::Result<()>;
+
+    /// Read the memory region [start, end) in big chunks. For every
+    /// chunk f is called with its address, its contents and the
+    /// number of bytes that belong to the chunk. Chunks hold up to
+    /// `overlap` more bytes after that, taken from the beginning of
+    /// the next chunk, so a value straddling two chunks can still be
+    /// read whole from the first one. The overlap never goes past
+    /// limit, which lets a memory map be split in parts that are read
+    /// separately. When a chunk fails to read (e.g. it contains a
+    /// guard page) it's read again page by page and only the
+    /// unreadable pages are skipped.
+    fn read_region<F>(&mut self, start: usize, end: usize, limit: usize, overlap: usize, mut f: F)
+    where
+        F: FnMut(usize, &[u8], usize),
+    {
+        let mut buffer = vec![0u8; CHUNK_SIZE.min(end.saturating_sub(start)) + overlap];
+        let mut read = |addr: usize, len: usize, buffer: &mut Vec<u8>| -> bool {
+            let with_overlap = (len + overlap).min(limit - addr);
+            if self.read_bytes(addr, &mut buffer[..with_overlap]).is_ok() {
+                f(addr, &buffer[..with_overlap], len);
+                true
+            } else if with_overlap > len && self.read_bytes(addr, &mut buffer[..len]).is_ok() {
+                // Next page is unreadable, values straddling it are lost
+                f(addr, &buffer[..len], len);
+                true
+            } else {
+                false
+            }
+        };
+
+        let mut addr = start;
+        while addr < end {
+            let len = CHUNK_SIZE.min(end - addr);
+            if !read(addr, len, &mut buffer) && len > PAGE_SIZE {
+                let mut page = addr;
+                while page < addr + len {
+                    let page_len = PAGE_SIZE.min(addr + len - page);
+                    read(page, page_len, &mut buffer);
+                    page += page_len;
+                }
+            }
+            addr += len;
+        }
+    }
 }
 
 /// Slowest naive memory reader. It's there mostly for having a simple
@@ -24,6 +87,10 @@ pub trait FromLeBytes: Sized {
     fn from_le_bytes(bytes: &[u8]) -> Self;
 }
 
+pub trait ToLeBytes: Sized {
+    fn to_le_bytes(self) -> Vec<u8>;
+}
+
 macro_rules! impl_from_le_bytes {
     ($type:ty) => {
         impl FromLeBytes for $type {
@@ -32,8 +99,15 @@ macro_rules! impl_from_le_bytes {
                 // `u32::from_le_bytes(bytes.try_into().unwrap())` but I want the
                 // conversion to be as fast as possible since it can be a bottleneck,
                 // so it's a major no-no for me. If you have a better safe way of
-                // doing this lemme know.
-                unsafe { *(bytes.as_ptr() as *const $type) }
+                // doing this lemme know. The read has to be unaligned since the
+                // bytes can come from any heap buffer.
+                unsafe { ptr::read_unaligned(bytes.as_ptr() as *const $type) }
+            }
+        }
+
+        impl ToLeBytes for $type {
+            fn to_le_bytes(self) -> Vec<u8> {
+                <$type>::to_le_bytes(self).to_vec()
             }
         }
     };
@@ -49,6 +123,8 @@ impl_from_le_bytes!(i16);
 impl_from_le_bytes!(u16);
 impl_from_le_bytes!(i8);
 impl_from_le_bytes!(u8);
+impl_from_le_bytes!(f64);
+impl_from_le_bytes!(f32);
 
 impl Clone for MemoryReaderSimple {
     fn clone(&self) -> Self {
@@ -84,24 +160,104 @@ impl MemoryReader for MemoryReaderSimple {
         let mut buffer = [0u8; mem::size_of::<T>()];
         // Fail silently when read is unsuccessful. This is for the
         // rare case when address stops existing between scans, for
-        // example because a memory region got munmapped. We can check
-        // instead on every read if it was done correctly, for example
-        // by returning an Option<T>, but I want to keep the inner
-        // scanning inner loop tight. Obviously this might end up with
-        // some incorrect results.
+        // example because a memory region got munmapped. Use try_read
+        // if you need to know whether the read was done correctly,
+        // this one is for keeping tight loops tight. Obviously this
+        // might end up with some incorrect results.
         let _ = self.mem_file.read_exact(&mut buffer);
 
         T::from_le_bytes(&buffer)
     }
 
-    fn write(&mut self, addr: usize, value: i32) {
-        self.mem_file.seek(SeekFrom::Start(addr as u64)).expect("a");
+    fn read_bytes(&mut self, addr: usize, buf: &mut [u8]) -> io::Result<()> {
+        self.mem_file.seek(SeekFrom::Start(addr as u64))?;
+        self.mem_file.read_exact(buf)
+    }
+
+    fn write<T: ToLeBytes>(&mut self, addr: usize, value: T) -> io::Result<()> {
+        self.write_bytes(addr, &value.to_le_bytes())
+    }
+
+    fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> io::Result<()> {
+        self.mem_file.seek(SeekFrom::Start(addr as u64))?;
+        self.mem_file.write_all(bytes)
+    }
+}
+
+/// Reader using `process_vm_readv`/`process_vm_writev`. There is one
+/// syscall per read and no seeking, so it is much faster than the
+/// simple reader, especially when reading in big chunks. Keep in mind
+/// that unlike writes through /proc/pid/mem it can't write to pages
+/// that aren't writable, like code.
+#[derive(Clone)]
+pub struct MemoryReaderVm {
+    pid: libc::pid_t,
+}
 
-        let bytes = value.to_le_bytes();
-        self.mem_file.write_all(&bytes).unwrap();
+impl MemoryReaderVm {
+    fn check_len(ret: isize, expected: usize) -> io::Result<()> {
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else if ret as usize != expected {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("only {} out of {} bytes transferred", ret, expected),
+            ))
+        } else {
+            Ok(())
+        }
     }
 }
 
+impl MemoryReader for MemoryReaderVm {
+    fn new(process: &Process) -> Self {
+        Self {
+            pid: process.pid as libc::pid_t,
+        }
+    }
+
+    fn read<T: Copy + FromLeBytes>(&mut self, addr: usize) -> T
+    where
+        [(); mem::size_of::<T>()]:,
+    {
+        let mut buffer = [0u8; mem::size_of::<T>()];
+        // Fails silently, same as MemoryReaderSimple::read
+        let _ = self.read_bytes(addr, &mut buffer);
+        T::from_le_bytes(&buffer)
+    }
+
+    fn read_bytes(&mut self, addr: usize, buf: &mut [u8]) -> io::Result<()> {
+        let local = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: addr as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let ret = unsafe { libc::process_vm_readv(self.pid, &local, 1, &remote, 1, 0) };
+        Self::check_len(ret, buf.len())
+    }
+
+    fn write<T: ToLeBytes>(&mut self, addr: usize, value: T) -> io::Result<()> {
+        self.write_bytes(addr, &value.to_le_bytes())
+    }
+
+    fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> io::Result<()> {
+        let local = libc::iovec {
+            iov_base: bytes.as_ptr() as *mut libc::c_void,
+            iov_len: bytes.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: addr as *mut libc::c_void,
+            iov_len: bytes.len(),
+        };
+        let ret = unsafe { libc::process_vm_writev(self.pid, &local, 1, &remote, 1, 0) };
+        Self::check_len(ret, bytes.len())
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
     use std::process;
@@ -114,5 +270,106 @@ mod tests {
         let a_addr = &a as *const i32;
         let ret = mem_reader.read::<i32>(a_addr as usize);
         assert_eq!(32, ret);
+        assert_eq!(mem_reader.try_read::<i32>(a_addr as usize), Some(32));
+        assert_eq!(mem_reader.try_read::<i32>(0), None);
+    }
+
+    #[test]
+    fn memory_reader_simple_bytes_test() {
+        let self_proc = Process::try_new(process::id()).unwrap();
+        let mut mem_reader = MemoryReaderSimple::new(&self_proc);
+        let a = Box::new([1u8, 2, 3, 4]);
+        let a_addr = a.as_ptr() as usize;
+        mem_reader.write_bytes(a_addr + 1, &[7, 8]).unwrap();
+        let mut buf = [0u8; 4];
+        mem_reader.read_bytes(a_addr, &mut buf).unwrap();
+        assert_eq!(buf, [1, 7, 8, 4]);
+        assert!(mem_reader.read_bytes(0, &mut buf).is_err());
+
+        mem_reader.write(a_addr, 0x0605u16).unwrap();
+        assert_eq!(mem_reader.read::<u32>(a_addr), 0x04080605);
+        assert!(mem_reader.write(0, 1u8).is_err());
+    }
+
+    #[test]
+    fn memory_reader_vm_test() {
+        let self_proc = Process::try_new(process::id()).unwrap();
+        let mut mem_reader = MemoryReaderVm::new(&self_proc);
+        let a = Box::new([1u32, 2, 3, 4]);
+        let a_addr = a.as_ptr() as usize;
+        assert_eq!(mem_reader.read::<u32>(a_addr + 4), 2);
+
+        mem_reader.write(a_addr + 8, 0xdeadu32).unwrap();
+        assert_eq!(mem_reader.read::<u32>(a_addr + 8), 0xdead);
+        assert!(mem_reader.write_bytes(0, &[1]).is_err());
+    }
+
+    #[test]
+    fn read_region_skips_unreadable_pages() {
+        let self_proc = Process::try_new(process::id()).unwrap();
+        let len = 3 * PAGE_SIZE;
+        let region = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(region, libc::MAP_FAILED);
+        let start = region as usize;
+        unsafe {
+            libc::mprotect((start + PAGE_SIZE) as *mut libc::c_void, PAGE_SIZE, libc::PROT_NONE);
+            *((start + 2 * PAGE_SIZE) as *mut u8) = 0x42;
+        }
+
+        let mut chunks = Vec::new();
+        let mut mem_reader = MemoryReaderVm::new(&self_proc);
+        mem_reader.read_region(start, start + len, start + len, 3, |addr, chunk, owned| {
+            chunks.push((addr, chunk.len(), owned, chunk[0]));
+        });
+        assert_eq!(
+            chunks,
+            vec![
+                (start, PAGE_SIZE, PAGE_SIZE, 0),
+                (start + 2 * PAGE_SIZE, PAGE_SIZE, PAGE_SIZE, 0x42)
+            ]
+        );
+
+        let mut chunks = Vec::new();
+        let mut mem_reader = MemoryReaderSimple::new(&self_proc);
+        mem_reader.read_region(start + 2 * PAGE_SIZE, start + len, start + len, 3, |addr, chunk, owned| {
+            chunks.push((addr, chunk.len(), owned));
+        });
+        assert_eq!(chunks, vec![(start + 2 * PAGE_SIZE, PAGE_SIZE, PAGE_SIZE)]);
+        unsafe { libc::munmap(region, len) };
+    }
+
+    #[test]
+    fn read_region_chunks_overlap() {
+        let self_proc = Process::try_new(process::id()).unwrap();
+        let region = vec![7u8; CHUNK_SIZE + PAGE_SIZE];
+        let start = region.as_ptr() as usize;
+        let mut chunks = Vec::new();
+        let mut mem_reader = MemoryReaderVm::new(&self_proc);
+        let end = start + region.len();
+        mem_reader.read_region(start, end, end, 7, |addr, chunk, owned| {
+            chunks.push((addr, chunk.len(), owned));
+        });
+        assert_eq!(
+            chunks,
+            vec![
+                (start, CHUNK_SIZE + 7, CHUNK_SIZE),
+                (start + CHUNK_SIZE, PAGE_SIZE, PAGE_SIZE)
+            ]
+        );
+
+        let mut chunks = Vec::new();
+        mem_reader.read_region(start, start + PAGE_SIZE, end, 7, |addr, chunk, owned| {
+            chunks.push((addr, chunk.len(), owned));
+        });
+        assert_eq!(chunks, vec![(start, PAGE_SIZE + 7, PAGE_SIZE)]);
     }
 }
@@ -0,0 +1,192 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Byte pattern like `48 8B 05 ?? ?? ?? ?? 89`. Every byte comes with
+/// a mask of the bits that have to match, so `??` matches anything,
+/// `4?` matches 0x40 to 0x4f and `4D/DF` matches 0x4d and 0x6d.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    masks: Vec<u8>,
+}
+
+impl Pattern {
+    /// Pattern matching exactly the given bytes
+    pub fn exact(bytes: Vec<u8>) -> Pattern {
+        let masks = vec![0xff; bytes.len()];
+        Pattern { bytes, masks }
+    }
+
+    /// Pattern matching the given bytes, with only the bits set in
+    /// masks having to match
+    pub fn masked(bytes: Vec<u8>, masks: Vec<u8>) -> Pattern {
+        assert_eq!(bytes.len(), masks.len());
+        Pattern { bytes, masks }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The bytes if the pattern has no wildcards
+    pub fn exact_bytes(&self) -> Option<&[u8]> {
+        self.masks
+            .iter()
+            .all(|&mask| mask == 0xff)
+            .then_some(&self.bytes)
+    }
+
+    /// bytes has to be at least as long as the pattern
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        self.bytes
+            .iter()
+            .zip(self.masks.iter())
+            .zip(bytes.iter())
+            .all(|((byte, mask), actual)| actual & mask == *byte)
+    }
+
+    /// Call f with the offset of every match in haystack that starts
+    /// in its first `owned` bytes. Candidates are found by looking
+    /// for the first byte without wildcards, so patterns shouldn't
+    /// start with a long run of them.
+    pub fn find_all<F: FnMut(usize)>(&self, haystack: &[u8], owned: usize, mut f: F) {
+        let len = self.len();
+        if len == 0 || haystack.len() < len {
+            return;
+        }
+        let last_start = owned.min(haystack.len() - len + 1);
+        match self.masks.iter().position(|&mask| mask == 0xff) {
+            Some(anchor) => {
+                let searched = &haystack[anchor..last_start + anchor];
+                for start in memchr::memchr_iter(self.bytes[anchor], searched) {
+                    if self.matches(&haystack[start..]) {
+                        f(start);
+                    }
+                }
+            }
+            None => (0..last_start)
+                .filter(|&start| self.matches(&haystack[start..]))
+                .for_each(f),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    /// Bytes are pairs of hex digits, optionally separated by spaces,
+    /// with `?` in place of unknown digits. A lone `?` stands for a
+    /// whole byte. A byte can be followed by `/` and the mask of the
+    /// bits that have to match, which `Display` uses for masks that
+    /// aren't made of whole digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = Vec::new();
+        let mut masks = Vec::new();
+        for token in s.split_whitespace() {
+            if let Some((byte, mask)) = token.split_once('/') {
+                let parse = |hex: &str| match hex.len() {
+                    2 => u8::from_str_radix(hex, 16)
+                        .map_err(|e| format!("couldn't parse {:?}: {:?}", hex, e)),
+                    _ => Err(format!("{:?} isn't a byte", hex)),
+                };
+                let mask = parse(mask)?;
+                bytes.push(parse(byte)? & mask);
+                masks.push(mask);
+                continue;
+            }
+            let token = if token == "?" { "??" } else { token };
+            if token.len() % 2 != 0 {
+                return Err(format!("{:?} isn't made of whole bytes", token));
+            }
+            for pair in token.as_bytes().chunks(2) {
+                let mut byte = 0;
+                let mut mask = 0;
+                for &digit in pair {
+                    let (value, digit_mask) = match digit {
+                        b'?' => (0, 0),
+                        _ => match (digit as char).to_digit(16) {
+                            Some(value) => (value as u8, 0xf),
+                            None => return Err(format!("{:?} isn't a hex digit", digit as char)),
+                        },
+                    };
+                    byte = byte << 4 | value;
+                    mask = mask << 4 | digit_mask;
+                }
+                bytes.push(byte);
+                masks.push(mask);
+            }
+        }
+        if bytes.is_empty() {
+            return Err("pattern is empty".to_string());
+        }
+        Ok(Pattern { bytes, masks })
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, (byte, mask)) in self.bytes.iter().zip(self.masks.iter()).enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            if [mask >> 4, mask & 0xf].iter().any(|digit| !matches!(digit, 0 | 0xf)) {
+                write!(f, "{:02X}/{:02X}", byte, mask)?;
+                continue;
+            }
+            for shift in [4, 0] {
+                match (mask >> shift) & 0xf {
+                    0xf => write!(f, "{:X}", (byte >> shift) & 0xf)?,
+                    _ => write!(f, "?")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_parse() {
+        let pattern: Pattern = "48 8b 05 ?? ?? ?4 3? 89".parse().unwrap();
+        assert_eq!(pattern.len(), 8);
+        assert_eq!(pattern.to_string(), "48 8B 05 ?? ?? ?4 3? 89");
+        assert!(pattern.exact_bytes().is_none());
+        assert_eq!("dead BEEF".parse::<Pattern>().unwrap().exact_bytes(), Some(&[0xde, 0xad, 0xbe, 0xef][..]));
+        assert_eq!("? 01".parse::<Pattern>().unwrap().to_string(), "?? 01");
+        assert!("4".parse::<Pattern>().is_err());
+        assert!("4g".parse::<Pattern>().is_err());
+        assert!("".parse::<Pattern>().is_err());
+
+        let folded = Pattern::masked(vec![0x4d, 0x00], vec![0xdf, 0xff]);
+        assert_eq!(folded.to_string(), "4D/DF 00");
+        assert_eq!(folded.to_string().parse::<Pattern>().unwrap(), folded);
+        assert!("4d/f".parse::<Pattern>().is_err());
+        assert!("4d/zz".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn pattern_find_all() {
+        let pattern: Pattern = "8B ?? 3?".parse().unwrap();
+        let haystack = [0x8b, 0x00, 0x31, 0x8b, 0x8b, 0x40, 0x3f, 0x8b, 0xff, 0x30];
+        let mut found = Vec::new();
+        pattern.find_all(&haystack, haystack.len(), |offset| found.push(offset));
+        assert_eq!(found, vec![0, 4, 7]);
+
+        // Matches have to start in the owned part
+        found.clear();
+        pattern.find_all(&haystack, 7, |offset| found.push(offset));
+        assert_eq!(found, vec![0, 4]);
+
+        let wildcards: Pattern = "?? ?f".parse().unwrap();
+        found.clear();
+        wildcards.find_all(&haystack, haystack.len(), |offset| found.push(offset));
+        assert_eq!(found, vec![5, 7]);
+    }
+}
@@ -0,0 +1,544 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::addresses::{run_workers, PART_SIZE};
+use crate::memory_reader::MemoryReader;
+use crate::process::{Module, Process};
+
+const POINTER_SIZE: usize = mem::size_of::<usize>();
+/// First bytes of saved pointer maps
+const MAGIC: &[u8; 8] = b"memoripm";
+const IO_BUFFER_SIZE: usize = 1024 * 1024;
+/// Longest module name accepted from a saved map, PATH_MAX on Linux
+const MAX_NAME_LEN: usize = 4096;
+
+/// Path from a module to an address that survives restarts: read the
+/// pointer at `module+module_offset`, add the first offset, read the
+/// pointer there, add the next offset and so on. The last offset
+/// leads to the address itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PointerChain {
+    pub module: String,
+    pub module_offset: usize,
+    pub offsets: Vec<usize>,
+}
+
+impl PointerChain {
+    /// Follow the chain in the process as it is now. None if the
+    /// module isn't loaded or a pointer on the way can't be read
+    pub fn resolve<U: MemoryReader>(
+        &self,
+        modules: &[Module],
+        memory_reader: &mut U,
+    ) -> Option<usize> {
+        let mut pointer = [0; POINTER_SIZE];
+        self.follow(modules, |addr| {
+            memory_reader.read_bytes(addr, &mut pointer).ok()?;
+            Some(usize::from_le_bytes(pointer))
+        })
+    }
+
+    /// Follow the chain with modules loaded where given, getting the
+    /// value of the pointers from read
+    fn follow<F>(&self, modules: &[Module], mut read: F) -> Option<usize>
+    where
+        F: FnMut(usize) -> Option<usize>,
+    {
+        let module = modules.iter().find(|module| module.name == self.module)?;
+        let mut addr = module.start + self.module_offset;
+        for offset in &self.offsets {
+            addr = read(addr)?.wrapping_add(*offset);
+        }
+        Some(addr)
+    }
+}
+
+impl fmt::Display for PointerChain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}+0x{:X}", self.module, self.module_offset)?;
+        for offset in &self.offsets {
+            write!(f, " -> +0x{:X}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every aligned, pointer-sized word in the process whose value
+/// points into readable memory
+pub struct PointerMap {
+    /// Pairs of (value, address of the pointer), sorted by value
+    pointers: Vec<(usize, usize)>,
+    modules: Vec<Module>,
+}
+
+impl PointerMap {
+    /// Read all readable, non-executable memory of the process. Code
+    /// is skipped, it's full of instructions that look like pointers.
+    pub fn build<U: MemoryReader>(
+        process: &Process,
+        threads: usize,
+        mut report_progress: Box<dyn FnMut(usize, usize)>,
+    ) -> PointerMap {
+        let readable: Vec<_> = process
+            .memory_maps
+            .iter()
+            .filter(|mm| mm.perms.read)
+            .map(|mm| (mm.addr_start, mm.addr_end))
+            .collect();
+        let is_readable = |value: usize| {
+            let idx = readable.partition_point(|&(_, end)| end <= value);
+            readable.get(idx).is_some_and(|&(start, _)| start <= value)
+        };
+        let parts: Vec<_> = process
+            .memory_maps
+            .iter()
+            .filter(|mm| mm.perms.read && !mm.perms.execute)
+            .flat_map(|mm| {
+                (mm.addr_start..mm.addr_end)
+                    .step_by(PART_SIZE)
+                    .map(move |start| (start, (start + PART_SIZE).min(mm.addr_end)))
+            })
+            .collect();
+        let to_scan = parts.iter().map(|(start, end)| end - start).sum();
+        let next_part = AtomicUsize::new(0);
+        let scanned = AtomicUsize::new(0);
+
+        let found = run_workers(threads, &scanned, to_scan, &mut report_progress, || {
+            let mut memory_reader = U::new(process);
+            let mut pointers = Vec::new();
+            while let Some(&(start, end)) = parts.get(next_part.fetch_add(1, Ordering::Relaxed)) {
+                memory_reader.read_region(start, end, end, 0, |chunk_addr, chunk, owned| {
+                    let first = (POINTER_SIZE - chunk_addr % POINTER_SIZE) % POINTER_SIZE;
+                    for offset in
+                        (first..owned.saturating_sub(POINTER_SIZE - 1)).step_by(POINTER_SIZE)
+                    {
+                        let bytes = chunk[offset..offset + POINTER_SIZE].try_into().unwrap();
+                        let value = usize::from_le_bytes(bytes);
+                        if is_readable(value) {
+                            pointers.push((value, chunk_addr + offset));
+                        }
+                    }
+                });
+                scanned.fetch_add(end - start, Ordering::Relaxed);
+            }
+            pointers
+        });
+
+        let mut pointers: Vec<_> = found.into_iter().flatten().collect();
+        pointers.sort_unstable();
+        report_progress(to_scan, to_scan);
+        PointerMap {
+            pointers,
+            modules: process.modules(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+
+    /// Pointers to anything in [target - max_offset, target]
+    fn pointers_to(&self, target: usize, max_offset: usize) -> &[(usize, usize)] {
+        let lower = self
+            .pointers
+            .partition_point(|&(value, _)| value < target.saturating_sub(max_offset));
+        let upper = self.pointers.partition_point(|&(value, _)| value <= target);
+        &self.pointers[lower..upper]
+    }
+
+    /// Find chains of at most depth pointers that lead to target and
+    /// start in a module, shortest ones first. Every pointer can add
+    /// at most max_offset to get to the next one. Only chains that
+    /// keep returns true for count as found.
+    pub fn find_chains<F>(
+        &self,
+        target: usize,
+        depth: usize,
+        max_offset: usize,
+        max_results: usize,
+        keep: F,
+    ) -> Vec<PointerChain>
+    where
+        F: Fn(&PointerChain) -> bool,
+    {
+        let graph = self.pointer_graph(target, depth, max_offset);
+        let mut chains = Vec::new();
+        for len in 1..=depth {
+            for &root in &graph.roots {
+                if graph.distance[&root] > len {
+                    continue;
+                }
+                let module = Module::find(&self.modules, root).unwrap();
+                let go_on = graph.paths(root, len, &mut Vec::new(), &mut |offsets| {
+                    let chain = PointerChain {
+                        module: module.name.clone(),
+                        module_offset: root - module.start,
+                        offsets: offsets.to_vec(),
+                    };
+                    if keep(&chain) {
+                        chains.push(chain);
+                    }
+                    chains.len() < max_results
+                });
+                if !go_on {
+                    return chains;
+                }
+            }
+        }
+        chains
+    }
+
+    /// Go back from target over at most depth pointers, following
+    /// every address once. Pointers in modules aren't followed
+    /// further, chains start there.
+    fn pointer_graph(&self, target: usize, depth: usize, max_offset: usize) -> PointerGraph {
+        let mut graph = PointerGraph {
+            target,
+            distance: HashMap::from([(target, 0)]),
+            next: HashMap::new(),
+            roots: Vec::new(),
+        };
+        let mut level = vec![target];
+        for distance in 1..=depth {
+            let mut next_level = Vec::new();
+            for &addr in &level {
+                for &(value, pointer) in self.pointers_to(addr, max_offset) {
+                    graph
+                        .next
+                        .entry(pointer)
+                        .or_default()
+                        .push((addr, addr - value));
+                    if graph.distance.contains_key(&pointer) {
+                        continue;
+                    }
+                    graph.distance.insert(pointer, distance);
+                    match Module::find(&self.modules, pointer) {
+                        Some(_) => graph.roots.push(pointer),
+                        None => next_level.push(pointer),
+                    }
+                }
+            }
+            level = next_level;
+        }
+        graph
+    }
+
+    /// Write the map to a file together with the address it was
+    /// scanned for, so later runs of the process can be checked
+    /// against it with `SavedPointerMap`
+    pub fn save(&self, path: &Path, target: usize) -> io::Result<()> {
+        let mut out = BufWriter::with_capacity(IO_BUFFER_SIZE, File::create(path)?);
+        out.write_all(MAGIC)?;
+        write_u64(&mut out, target)?;
+        write_u64(&mut out, self.modules.len())?;
+        for module in &self.modules {
+            write_u64(&mut out, module.name.len())?;
+            out.write_all(module.name.as_bytes())?;
+            write_u64(&mut out, module.start)?;
+            write_u64(&mut out, module.end)?;
+        }
+        write_u64(&mut out, self.pointers.len())?;
+        for &(value, addr) in &self.pointers {
+            write_u64(&mut out, addr)?;
+            write_u64(&mut out, value)?;
+        }
+        out.flush()
+    }
+}
+
+/// Pointers that lead to a target, found by `PointerMap::pointer_graph`
+struct PointerGraph {
+    target: usize,
+    /// Fewest pointers from every address found to the target
+    distance: HashMap<usize, usize>,
+    /// Addresses every pointer leads to, with the offset added to its
+    /// value to get there
+    next: HashMap<usize, Vec<(usize, usize)>>,
+    /// Pointers in modules, in the order they were found
+    roots: Vec<usize>,
+}
+
+impl PointerGraph {
+    /// Call f with the offsets of every path of exactly len pointers
+    /// from addr to the target, until it returns false. Returns
+    /// whether f always returned true.
+    fn paths<F>(&self, addr: usize, len: usize, offsets: &mut Vec<usize>, f: &mut F) -> bool
+    where
+        F: FnMut(&[usize]) -> bool,
+    {
+        if len == 0 {
+            return addr != self.target || f(offsets);
+        }
+        if addr == self.target {
+            return true;
+        }
+        for &(next, offset) in self.next.get(&addr).into_iter().flatten() {
+            // Skip what can't get to the target in the pointers left
+            if self.distance[&next] >= len {
+                continue;
+            }
+            offsets.push(offset);
+            let go_on = self.paths(next, len - 1, offsets, f);
+            offsets.pop();
+            if !go_on {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Pointer map of an earlier run of the process, where modules were
+/// loaded at other addresses
+pub struct SavedPointerMap {
+    /// Address that was scanned for in that run
+    target: usize,
+    modules: Vec<Module>,
+    /// Pairs of (address of the pointer, value), sorted by address
+    pointers: Vec<(usize, usize)>,
+}
+
+impl SavedPointerMap {
+    pub fn load(path: &Path) -> io::Result<SavedPointerMap> {
+        let file = File::open(path)?;
+        // Counts can't be more than what fits in the file, so corrupt
+        // ones are caught before allocating for them
+        let file_len = file.metadata()?.len() as usize;
+        let mut input = BufReader::with_capacity(IO_BUFFER_SIZE, file);
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} isn't a pointer map", path.display()),
+            ));
+        }
+        let target = read_u64(&mut input)?;
+        let modules = (0..read_len(&mut input, file_len / 24)?)
+            .map(|_| {
+                let mut name = vec![0; read_len(&mut input, MAX_NAME_LEN)?];
+                input.read_exact(&mut name)?;
+                Ok(Module {
+                    name: String::from_utf8_lossy(&name).into_owned(),
+                    start: read_u64(&mut input)?,
+                    end: read_u64(&mut input)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        let mut pointers = (0..read_len(&mut input, file_len / 16)?)
+            .map(|_| Ok((read_u64(&mut input)?, read_u64(&mut input)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        pointers.sort_unstable();
+        Ok(SavedPointerMap {
+            target,
+            modules,
+            pointers,
+        })
+    }
+
+    /// Whether chain led to the target in that run. Every pointer
+    /// on the way has to be in the map, so chains can be checked
+    /// without the process.
+    pub fn leads_to_target(&self, chain: &PointerChain) -> bool {
+        let value_at = |addr| {
+            let idx = self
+                .pointers
+                .binary_search_by_key(&addr, |&(addr, _)| addr)
+                .ok()?;
+            Some(self.pointers[idx].1)
+        };
+        chain.follow(&self.modules, value_at) == Some(self.target)
+    }
+}
+
+fn write_u64(out: &mut impl Write, value: usize) -> io::Result<()> {
+    out.write_all(&(value as u64).to_le_bytes())
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<usize> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes) as usize)
+}
+
+/// Read a length or count, which can't be more than max
+fn read_len(input: &mut impl Read, max: usize) -> io::Result<usize> {
+    let len = read_u64(input)?;
+    if len > max {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("corrupt pointer map, length {} is over {}", len, max),
+        ));
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_reader::MemoryReaderVm;
+    use std::process;
+    use std::sync::atomic::AtomicUsize;
+
+    static ROOT: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn pointer_scan_finds_static_chain() {
+        let target = Box::new([0u64, 0x5ca1ab1e, 0, 0]);
+        let target_addr = &target[1] as *const u64 as usize;
+        let middle = Box::new([0usize, 0, 0, target.as_ptr() as usize]);
+        ROOT.store(middle.as_ptr() as usize, Ordering::SeqCst);
+        let process = Process::try_new(process::id()).unwrap();
+
+        let map = PointerMap::build::<MemoryReaderVm>(&process, 2, Box::new(|_, _| ()));
+        assert!(!map.is_empty());
+        let chains = map.find_chains(target_addr, 2, 0x100, usize::MAX, |_| true);
+        let root_addr = &ROOT as *const AtomicUsize as usize;
+        let root_module = Module::find(&map.modules, root_addr).unwrap();
+        let expected = PointerChain {
+            module: root_module.name.clone(),
+            module_offset: root_addr - root_module.start,
+            offsets: vec![0x18, 0x8],
+        };
+        assert!(chains.contains(&expected));
+        assert!(expected.to_string().ends_with(" -> +0x18 -> +0x8"));
+
+        let mut memory_reader = MemoryReaderVm::new(&process);
+        let modules = process.modules();
+        assert_eq!(
+            expected.resolve(&modules, &mut memory_reader),
+            Some(target_addr)
+        );
+        std::hint::black_box((target, middle));
+    }
+
+    #[test]
+    fn saved_pointer_map_rejects_corrupt_lengths() {
+        let path = std::env::temp_dir().join(format!("memori-corrupt-{}.pm", process::id()));
+        let mut map = MAGIC.to_vec();
+        for number in [0x1000u64, 1, u64::MAX / 2] {
+            map.extend_from_slice(&number.to_le_bytes());
+        }
+        std::fs::write(&path, &map).unwrap();
+        let err = SavedPointerMap::load(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Too many modules for the size of the file
+        map.truncate(MAGIC.len() + 8);
+        map.extend_from_slice(&1000u64.to_le_bytes());
+        std::fs::write(&path, &map).unwrap();
+        let err = SavedPointerMap::load(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn find_chains_keeps_chains_through_shared_addresses() {
+        // 0x30000 points right at 0x40000, so it's reached from the
+        // pointers at 0x40008 and 0x40000 with different offsets
+        let map = PointerMap {
+            pointers: vec![
+                (0x30000, 0x1010),
+                (0x40000, 0x30000),
+                (0x4ffe0, 0x40008),
+                (0x4fff0, 0x40000),
+            ],
+            modules: vec![Module {
+                name: "libgame.so".to_string(),
+                start: 0x1000,
+                end: 0x2000,
+            }],
+        };
+        let chains = map.find_chains(0x50000, 3, 0x100, usize::MAX, |_| true);
+        assert_eq!(chains.len(), 2);
+        let chains = map.find_chains(0x50000, 3, 0x100, usize::MAX, |chain| {
+            chain.offsets == [0, 0, 0x10]
+        });
+        assert_eq!(chains.len(), 1);
+    }
+
+    #[test]
+    fn find_chains_follows_every_address_once() {
+        // Five levels of two pointers, both pointing at the next two,
+        // so there are 2^5 ways from the module to the target
+        let level = |idx: usize| 0x10000 + idx * 0x1000;
+        let mut pointers = vec![(level(0), 0x1010)];
+        for idx in 0..5 {
+            let value = if idx == 4 {
+                0x90000 - 8
+            } else {
+                level(idx + 1)
+            };
+            pointers.push((value, level(idx)));
+            pointers.push((value, level(idx) + 8));
+        }
+        pointers.sort_unstable();
+        let map = PointerMap {
+            pointers,
+            modules: vec![Module {
+                name: "libgame.so".to_string(),
+                start: 0x1000,
+                end: 0x2000,
+            }],
+        };
+
+        let graph = map.pointer_graph(0x90000, 8, 0x10);
+        // The target, two addresses per level and the module pointer
+        assert_eq!(graph.distance.len(), 12);
+        let chains = map.find_chains(0x90000, 8, 0x10, usize::MAX, |_| true);
+        assert_eq!(chains.len(), 32);
+        let chains = map.find_chains(0x90000, 8, 0x10, 5, |_| true);
+        assert_eq!(chains.len(), 5);
+        let chains = map.find_chains(0x90000, 8, 0x10, usize::MAX, |chain| {
+            chain.offsets == [8, 8, 8, 8, 8, 8]
+        });
+        assert_eq!(chains.len(), 1);
+        assert!(map
+            .find_chains(0x90000, 5, 0x10, usize::MAX, |_| true)
+            .is_empty());
+    }
+
+    #[test]
+    fn saved_pointer_map_intersects_runs() {
+        // The same structure in a run where the module got loaded 0x5000
+        // higher and the heap is somewhere else
+        let module = |start| Module {
+            name: "libgame.so".to_string(),
+            start,
+            end: start + 0x1000,
+        };
+        let map = PointerMap {
+            pointers: vec![(0x9000, 0x6020), (0x9100, 0x6028)],
+            modules: vec![module(0x6000)],
+        };
+        let path = std::env::temp_dir().join(format!("memori-test-{}.ptr", process::id()));
+        map.save(&path, 0x9018).unwrap();
+        let saved = SavedPointerMap::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let live = PointerMap {
+            pointers: vec![(0x20000, 0x1020), (0x20008, 0x1028)],
+            modules: vec![module(0x1000)],
+        };
+        let chains = live.find_chains(0x20018, 1, 0x100, usize::MAX, |_| true);
+        assert_eq!(chains.len(), 2);
+        let chains = live.find_chains(0x20018, 1, 0x100, usize::MAX, |chain| {
+            saved.leads_to_target(chain)
+        });
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].to_string(), "libgame.so+0x20 -> +0x18");
+
+        std::fs::write(&path, b"not a pointer map").unwrap();
+        assert!(SavedPointerMap::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
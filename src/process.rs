@@ -4,6 +4,25 @@ use std::fs::File;
 use std::io::{self};
 use std::path::PathBuf;
 
+/// A file mapped into the process, like the executable or a shared
+/// library. It spans the adjacent memory maps of the file, plus the
+/// anonymous map right after them, which holds its `.bss`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    /// File name without the directories
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Module {
+    /// Module that addr is in, modules have to be sorted by address
+    pub fn find(modules: &[Module], addr: usize) -> Option<&Module> {
+        let idx = modules.partition_point(|module| module.end <= addr);
+        modules.get(idx).filter(|module| module.start <= addr)
+    }
+}
+
 pub struct Process {
     pub pid: u32,
     pub command: String,
@@ -22,7 +41,17 @@ impl Process {
         let maps_file = File::open(maps_path)?;
         let maps = io::read_to_string(maps_file)?;
 
-        let memory_maps: Vec<_> = maps.lines().map(MemoryMap::from).collect();
+        let memory_maps = maps
+            .lines()
+            .map(|line| {
+                line.parse::<MemoryMap>().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("couldn't parse memory map {:?}: {}", line, e),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
 
         Ok(Process {
             pid,
@@ -30,4 +59,81 @@ impl Process {
             memory_maps,
         })
     }
+
+    /// Modules sorted by address. A module starts at the map of its
+    /// file with offset 0 and goes on over the maps of the same file
+    /// that follow it directly with increasing offsets, so a file
+    /// mapped twice is two modules instead of one spanning whatever
+    /// is in between.
+    pub fn modules(&self) -> Vec<Module> {
+        let mut modules = Vec::new();
+        // Module being built, with its path and the offset of its
+        // last map
+        let mut current: Option<(&str, usize, Module)> = None;
+        for mm in &self.memory_maps {
+            if let Some((path, offset, module)) = &mut current {
+                let follows = module.end == mm.addr_start;
+                if follows && mm.pathname == *path && mm.offset > *offset {
+                    module.end = mm.addr_end;
+                    *offset = mm.offset;
+                    continue;
+                }
+                if follows && mm.pathname.is_empty() {
+                    module.end = mm.addr_end;
+                }
+                modules.extend(current.take().map(|(_, _, module)| module));
+            }
+            if mm.pathname.starts_with('/') && mm.offset == 0 {
+                let name = mm.pathname.rsplit('/').next().unwrap_or_default();
+                let module = Module {
+                    name: name.to_string(),
+                    start: mm.addr_start,
+                    end: mm.addr_end,
+                };
+                current = Some((&mm.pathname, mm.offset, module));
+            }
+        }
+        modules.extend(current.map(|(_, _, module)| module));
+        modules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modules_from_maps() {
+        let maps = "\
+            00400000-00401000 r--p 00000000 08:02 1 /usr/bin/game
+            00401000-00405000 r-xp 00001000 08:02 1 /usr/bin/game
+            00405000-00406000 rw-p 00005000 08:02 1 /usr/bin/game
+            00406000-00408000 rw-p 00000000 00:00 0
+            00408000-00409000 rw-p 00000000 00:00 0
+            01000000-01100000 rw-p 00000000 00:00 0 [heap]
+            7f0000000000-7f0000001000 r--p 00000000 08:02 2 /data/level.pak
+            7f0000001000-7f0000002000 rw-p 00000000 00:00 0
+            7f0000100000-7f0000101000 r--p 00000000 08:02 2 /data/level.pak
+            7f0000200000-7f0000201000 r--p 00004000 08:02 2 /data/level.pak
+            7f0000300000-7f0000301000 rw-s 00000000 00:01 3 /memfd:state (deleted)";
+        let process = Process {
+            pid: 0,
+            command: String::new(),
+            memory_maps: maps.lines().map(|line| line.parse().unwrap()).collect(),
+        };
+        let module = |name: &str, start, end| Module {
+            name: name.to_string(),
+            start,
+            end,
+        };
+        assert_eq!(
+            process.modules(),
+            vec![
+                module("game", 0x400000, 0x408000),
+                module("level.pak", 0x7f0000000000, 0x7f0000002000),
+                module("level.pak", 0x7f0000100000, 0x7f0000101000),
+                module("memfd:state", 0x7f0000300000, 0x7f0000301000),
+            ]
+        );
+    }
 }
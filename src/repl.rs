@@ -1,6 +1,7 @@
-use crate::commands::{Cli, Command};
+use crate::commands::{Cli, Command, TableAction};
 use crate::context::Context;
-use crate::{addresses, animations, util};
+use crate::history::History;
+use crate::{animations, util};
 
 use clap::Parser;
 use owo_colors::colors::{Red, Yellow};
@@ -99,39 +100,144 @@ impl Repl {
                     }
                 }
                 
-                let scan_expr = util::filter_args_to_scan_expr(filter_args);
+                let scan_expr = match util::filter_args_to_scan_expr(filter_args, ctx.val_type.unwrap()) {
+                    Ok(scan_expr) => scan_expr,
+                    Err(err) => {
+                        return Message {
+                            message: err,
+                            is_error: true,
+                        }
+                    }
+                };
                 
+                let baseline = util::filter_args_to_baseline(filter_args);
+
+                ctx.prepare_scan(&scan_expr);
                 // Little weird to satisfy the borrow checker
+                let mut result = Ok(0);
+                let mut history_error = None;
                 if let Some(mut addrs) = ctx.addrs.take() {
                     let (tx, rx) = mpsc::channel();
                     let thread = thread::spawn(move || {
                         animations::bar::game_of_life(rx);
                     });
-                    addrs.scan(
+                    let before = match ctx.config.history_len {
+                        0 => None,
+                        _ => addrs
+                            .try_clone_box()
+                            .map_err(|err| history_error = Some(err))
+                            .ok(),
+                    };
+                    result = addrs.scan(
                         ctx,
                         &scan_expr,
+                        &baseline,
                         Box::new(move |scanned, to_scan| {
-                            tx.send((scanned, to_scan)).unwrap();
+                            // The animation stops listening once it sees 100%
+                            let _ = tx.send((scanned, to_scan));
                         }),
                     );
                     ctx.addrs = Some(addrs);
                     thread.join().unwrap();
+                    if result.is_ok() {
+                        let expr = filter_args.expr.join(" ");
+                        match before {
+                            Some(before) => {
+                                ctx.history.record(before, expr, ctx.config.history_len)
+                            }
+                            None => ctx.history = History::new(expr),
+                        }
+                    }
+                }
+                let dropped = match result {
+                    Ok(dropped) => dropped,
+                    Err(err) => {
+                        return Message {
+                            message: err,
+                            is_error: true,
+                        }
+                    }
+                };
+                let mut message = format!(
+                    "scanner found {} addresses",
+                    ctx.addrs.as_ref().unwrap().len()
+                );
+                if dropped > 0 {
+                    message += &format!(" ({} dropped because they became unreadable)", dropped);
+                }
+                // Without the result set from before, nothing can be undone
+                if let Some(err) = &history_error {
+                    message += &format!("\nundo history got cleared: {}", err);
                 }
                 Message {
-                    message: format!(
-                        "scanner found {} addresses",
-                        ctx.addrs.as_ref().unwrap().len()
-                    ),
-                    is_error: false,
+                    message,
+                    is_error: history_error.is_some(),
                 }
             }
             Command::Print => {
-                util::print_addrs(ctx.addrs.as_mut().unwrap());
+                let modules = ctx.process.as_ref().map(|proc| proc.modules()).unwrap_or_default();
+                match util::print_addrs(ctx.addrs.as_mut().unwrap(), &modules) {
+                    Ok(()) => Message {
+                        message: "".to_string(),
+                        is_error: false,
+                    },
+                    Err(err) => Message {
+                        message: err,
+                        is_error: true,
+                    },
+                }
+            }
+            Command::Undo => match ctx.undo() {
+                Ok(len) => Message {
+                    message: format!("back to {} addresses", len),
+                    is_error: false,
+                },
+                Err(err) => Message {
+                    message: err,
+                    is_error: true,
+                },
+            },
+            Command::Redo => match ctx.redo() {
+                Ok(len) => Message {
+                    message: format!("forward to {} addresses", len),
+                    is_error: false,
+                },
+                Err(err) => Message {
+                    message: err,
+                    is_error: true,
+                },
+            },
+            Command::History => {
+                util::print_history(ctx);
                 Message {
                     message: "".to_string(),
                     is_error: false,
                 }
             }
+            Command::Snapshot(snapshot_args) => {
+                let Some(addrs) = ctx.addrs.as_mut() else {
+                    return Message {
+                        message: "You have to select a type first".to_string(),
+                        is_error: false,
+                    };
+                };
+                match &snapshot_args.name {
+                    Some(name) => match addrs.snapshot(name) {
+                        Ok(()) => Message {
+                            message: format!("saved snapshot {:?}", name),
+                            is_error: false,
+                        },
+                        Err(err) => Message {
+                            message: err,
+                            is_error: true,
+                        },
+                    },
+                    None => Message {
+                        message: addrs.snapshot_names().join("\n"),
+                        is_error: false,
+                    },
+                }
+            }
             Command::Exit => {
                 ctx.quit = true;
                 Message {
@@ -139,15 +245,143 @@ impl Repl {
                     is_error: false,
                 }
             },
-            Command::Set(set_args) => {
-                ctx.addrs.as_mut().unwrap().write(set_args.value.parse().unwrap(), set_args.selected);
+            Command::Select(select_args) => match ctx.select(select_args) {
+                Ok(entry) => Message {
+                    message: format!("selected {:x}", entry.addr),
+                    is_error: false,
+                },
+                Err(err) => Message {
+                    message: err,
+                    is_error: true,
+                },
+            },
+            Command::Unselect(unselect_args) => match ctx.unselect(unselect_args) {
+                Ok(entry) => Message {
+                    message: format!("unselected {:x}", entry.addr),
+                    is_error: false,
+                },
+                Err(err) => Message {
+                    message: err,
+                    is_error: true,
+                },
+            },
+            Command::Selected => {
+                util::print_selected(ctx);
+                Message {
+                    message: "".to_string(),
+                    is_error: false,
+                }
+            }
+            Command::Set(set_args) => match ctx.set(set_args) {
+                Ok(()) => Message {
+                    message: "".to_string(),
+                    is_error: false,
+                },
+                Err(err) => Message {
+                    message: err,
+                    is_error: true,
+                },
+            },
+            Command::Freeze(freeze_args) => match ctx.freeze(freeze_args) {
+                Ok(entry) => Message {
+                    message: format!(
+                        "froze {:x} at {}",
+                        entry.addr,
+                        entry.val_type.format_bytes(&entry.value)
+                    ),
+                    is_error: false,
+                },
+                Err(err) => Message {
+                    message: err,
+                    is_error: true,
+                },
+            },
+            Command::Unfreeze(unfreeze_args) => match ctx.unfreeze(unfreeze_args) {
+                Ok(entry) => Message {
+                    message: format!("unfroze {:x}", entry.addr),
+                    is_error: false,
+                },
+                Err(err) => Message {
+                    message: err,
+                    is_error: true,
+                },
+            },
+            Command::Frozen => {
+                if let Some(freezer) = &ctx.freezer {
+                    util::print_frozen(&freezer.entries());
+                }
+                Message {
+                    message: "".to_string(),
+                    is_error: false,
+                }
+            }
+            Command::PointerScan(pointer_scan_args) => {
+                let (tx, rx) = mpsc::channel();
+                let thread = thread::spawn(move || {
+                    animations::bar::game_of_life(rx);
+                });
+                let result = ctx.pointer_scan(
+                    pointer_scan_args,
+                    Box::new(move |scanned, to_scan| {
+                        let _ = tx.send((scanned, to_scan));
+                    }),
+                );
+                thread.join().unwrap();
+                match result {
+                    Ok(chains) => {
+                        util::print_pointer_chains(&chains);
+                        Message {
+                            message: format!("found {} pointer chains", chains.len()),
+                            is_error: false,
+                        }
+                    }
+                    Err(err) => Message {
+                        message: err,
+                        is_error: true,
+                    },
+                }
+            }
+            Command::Table(table_args) => match &table_args.action {
+                TableAction::Save { file } => match ctx.table_save(file) {
+                    Ok(len) => Message {
+                        message: format!("saved {} entries", len),
+                        is_error: false,
+                    },
+                    Err(err) => Message {
+                        message: err,
+                        is_error: true,
+                    },
+                },
+                TableAction::Load { file } | TableAction::Import { file } => {
+                    let loaded = match &table_args.action {
+                        TableAction::Import { .. } => ctx.table_import(file),
+                        _ => ctx.table_load(file),
+                    };
+                    match loaded {
+                        Ok((loaded, problems)) => {
+                            let mut message = format!("loaded {} entries", loaded);
+                            for problem in problems {
+                                message += &format!("\ncouldn't load {}", problem);
+                            }
+                            Message {
+                                message,
+                                is_error: false,
+                            }
+                        }
+                        Err(err) => Message {
+                            message: err,
+                            is_error: true,
+                        },
+                    }
+                }
+            },
+            Command::Config(config_args) => {
+                ctx.config(config_args);
                 Message {
                     message: "".to_string(),
                     is_error: false,
                 }
             }
-            
-            _ => panic!("Impossible command"),
         }
     }
 
@@ -166,7 +400,7 @@ impl Repl {
             }
         }
         
-        println!("");
+        println!();
     }
 
     pub fn repl(&mut self) {
@@ -0,0 +1,95 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::{FreezeMode, ValType};
+
+/// Cheat table, a TOML file with the addresses worth keeping between
+/// sessions. Addresses are expressions like `[libgame.so+1A2B0]+18`,
+/// so they're found again after the process restarted.
+///
+/// ```toml
+/// [[entry]]
+/// label = "health"
+/// address = "[libgame.so+1a2b0]+18"
+/// type = "i32"
+/// frozen = true
+/// value = "100"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    #[serde(default, rename = "entry")]
+    pub entries: Vec<TableEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableEntry {
+    #[serde(default)]
+    pub label: String,
+    pub address: String,
+    #[serde(rename = "type")]
+    pub val_type: ValType,
+    /// Size in bytes, only needed by types without a fixed one like
+    /// byte arrays and strings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    #[serde(default)]
+    pub frozen: bool,
+    /// Value to freeze at, the current one when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freeze_mode: Option<FreezeMode>,
+    /// Notes about the entry, kept but not used
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+impl Table {
+    pub fn load(path: &Path) -> Result<Table, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("couldn't parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, content).map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_parse() {
+        let table: Table = toml::from_str(
+            r#"
+            [[entry]]
+            label = "health"
+            address = "[libgame.so+1a2b0]+18"
+            type = "i32"
+            frozen = true
+            value = "100"
+
+            [[entry]]
+            address = "7ffd1000"
+            type = "utf16"
+            size = 32
+            description = "player name"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table.entries.len(), 2);
+        assert_eq!(table.entries[0].val_type, ValType::I32);
+        assert_eq!(table.entries[0].freeze_mode, None);
+        assert_eq!(table.entries[1].size, Some(32));
+        assert!(!table.entries[1].frozen);
+
+        let saved = toml::to_string(&table).unwrap();
+        assert_eq!(toml::from_str::<Table>(&saved).unwrap(), table);
+        assert!(toml::from_str::<Table>("[[entry]]\naddress = \"10\"\ntype = \"i33\"").is_err());
+    }
+}
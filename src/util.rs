@@ -1,35 +1,168 @@
 use owo_colors::OwoColorize;
 
-use crate::addresses::{Addresses, ScanExpr};
-use crate::commands::{FilterArgs, FilterOperator};
-
-pub fn filter_args_to_scan_expr(filter_args: &FilterArgs) -> ScanExpr {
-    let operand = filter_args.operand.as_ref();
-    match filter_args.operator {
-        FilterOperator::Less => ScanExpr::Less(operand.unwrap().clone()),
-        FilterOperator::LessEqual => {
-            ScanExpr::LessEqual(operand.unwrap().clone())
-        }
-        FilterOperator::Greater => ScanExpr::Greater(operand.unwrap().clone()),
-        FilterOperator::GreaterEqual => {
-            ScanExpr::GreaterEqual(operand.unwrap().clone())
-        }
-        FilterOperator::Equal => ScanExpr::Equal(operand.unwrap().clone()),
-        FilterOperator::NotEqual => {
-            ScanExpr::NotEqual(operand.unwrap().clone())
-        }
-        FilterOperator::Changed => ScanExpr::Changed,
-        FilterOperator::NotChanged => ScanExpr::NotChanged,
-        FilterOperator::Unknown => ScanExpr::Unknown,
+use crate::address_expr::AddressExpr;
+use crate::addresses::{Addresses, Baseline, Bounds, FloatCmp, ScanExpr};
+use crate::commands::{FilterArgs, FreezeMode, Rounding, ValType};
+use crate::context::Context;
+use crate::filter_parser::FilterParser;
+use crate::freezer::FrozenEntry;
+use crate::pointer_scan::PointerChain;
+use crate::process::Module;
+use crate::memory_reader::{MemoryReader, MemoryReaderSimple};
+
+fn filter_args_to_float_cmp(filter_args: &FilterArgs) -> FloatCmp {
+    if let Some(epsilon) = filter_args.epsilon {
+        return FloatCmp::Epsilon(epsilon);
     }
+    match filter_args.rounding {
+        None => FloatCmp::default(),
+        Some(Rounding::Exact) => FloatCmp::Exact,
+        Some(Rounding::Rounded) => FloatCmp::Rounded,
+        Some(Rounding::Truncated) => FloatCmp::Truncated,
+        Some(Rounding::ExtremeRounded) => FloatCmp::ExtremeRounded,
+    }
+}
+
+/// Byte values have spaces in them, so they aren't parsed as a filter
+/// expression. `== <bytes>` and `!= <bytes>` take all words after the
+/// operator, words without an operator are searched for. Strings are
+/// turned into byte patterns here, so storing them works the same.
+fn filter_args_to_bytes_expr(
+    filter_args: &FilterArgs,
+    val_type: ValType,
+) -> Result<ScanExpr, String> {
+    let operand = |words: &[String]| match val_type.is_text() {
+        true => val_type
+            .text_pattern(&words.join(" "), filter_args.ignore_case, filter_args.prefix)
+            .map(|pattern| pattern.to_string()),
+        false => Ok(words.join(" ")),
+    };
+    let words = &filter_args.expr;
+    Ok(match words.first().map(|word| word.as_str()) {
+        Some("changed") if words.len() == 1 => ScanExpr::Changed,
+        Some("not-changed") if words.len() == 1 => ScanExpr::NotChanged,
+        Some("==") => ScanExpr::Equal(operand(&words[1..])?, FloatCmp::Exact),
+        Some("!=") => ScanExpr::NotEqual(operand(&words[1..])?, FloatCmp::Exact),
+        _ => ScanExpr::Equal(operand(words)?, FloatCmp::Exact),
+    })
 }
 
-pub fn print_addrs(addrs: &mut Box<dyn Addresses>) {
-    for (idx, (addr, old_val, new_val)) in addrs.get_vals_to_print().iter().enumerate() {
+pub fn filter_args_to_scan_expr(
+    filter_args: &FilterArgs,
+    val_type: ValType,
+) -> Result<ScanExpr, String> {
+    if val_type.size().is_none() {
+        return filter_args_to_bytes_expr(filter_args, val_type);
+    }
+    let float_cmp = filter_args_to_float_cmp(filter_args);
+    let bounds = if filter_args.exclusive {
+        Bounds::Exclusive
+    } else {
+        Bounds::Inclusive
+    };
+    let expr = FilterParser::new(&filter_args.expr, float_cmp, bounds).parse()?;
+    expr.check_operands(val_type)?;
+    Ok(expr)
+}
+
+pub fn filter_args_to_baseline(filter_args: &FilterArgs) -> Baseline {
+    match filter_args.baseline.as_deref() {
+        None | Some("previous") => Baseline::Previous,
+        Some("first") => Baseline::First,
+        Some(baseline) => match baseline.parse() {
+            Ok(pass) => Baseline::Pass(pass),
+            Err(_) => Baseline::Snapshot(baseline.to_string()),
+        },
+    }
+}
+
+/// How many addresses `print` shows at most
+const PRINT_MAX: usize = 1000;
+
+/// Addresses inside modules are printed relative to them, so they
+/// can be told apart after the process restarts
+pub fn print_addrs(addrs: &mut Box<dyn Addresses>, modules: &[Module]) -> Result<(), String> {
+    let vals = addrs.get_vals_to_print(PRINT_MAX)?;
+    for (idx, (addr, old_val, new_val)) in vals.iter().enumerate() {
+        let addr = AddressExpr::relative_to_module(modules, *addr);
         if old_val == new_val {
-            println!("{:3}: {:x}\t{}\t{}", idx, addr, old_val, new_val);
+            println!("{:3}: {}\t{}\t{}", idx, addr, old_val, new_val);
+        } else {
+            println!("{:3}: {}\t{}\t{}", idx, addr, old_val, new_val.red());
+        }
+    }
+    if addrs.len() > PRINT_MAX {
+        println!("... and {} more", addrs.len() - PRINT_MAX);
+    }
+    Ok(())
+}
+
+pub fn print_pointer_chains(chains: &[PointerChain]) {
+    for (idx, chain) in chains.iter().enumerate() {
+        println!("{:3}: {}", idx, chain);
+    }
+}
+
+pub fn print_frozen(entries: &[FrozenEntry]) {
+    for (idx, entry) in entries.iter().enumerate() {
+        let mode = match entry.mode {
+            FreezeMode::Exact => "",
+            FreezeMode::Increase => "(allow increase)",
+            FreezeMode::Decrease => "(allow decrease)",
+        };
+        println!(
+            "{:3}: {:x}\t{}\t{}\t{}",
+            idx,
+            entry.addr,
+            entry.val_type.name(),
+            entry.val_type.format_bytes(&entry.value),
+            mode
+        );
+    }
+}
+
+pub fn print_selected(ctx: &Context) {
+    let Some(proc) = &ctx.process else {
+        return;
+    };
+    let mut memory_reader = MemoryReaderSimple::new(proc);
+    let modules = proc.modules();
+    let frozen: Vec<_> = match &ctx.freezer {
+        Some(freezer) => freezer.entries().iter().map(|e| e.addr).collect(),
+        None => Vec::new(),
+    };
+    for (idx, entry) in ctx.watch_list.iter().enumerate() {
+        let mut value = vec![0; entry.size];
+        let value = match memory_reader.read_bytes(entry.addr, &mut value) {
+            Ok(()) => entry.val_type.format_bytes(&value),
+            Err(_) => "??".to_string(),
+        };
+        let frozen = if frozen.contains(&entry.addr) {
+            "(frozen)"
+        } else {
+            ""
+        };
+        println!(
+            "{:3}: {}\t{}\t{}\t{}\t{}",
+            idx,
+            AddressExpr::relative_to_module(&modules, entry.addr),
+            entry.val_type.name(),
+            value,
+            entry.label,
+            frozen.blue()
+        );
+    }
+}
+
+pub fn print_history(ctx: &Context) {
+    let len = ctx.addrs.as_ref().map_or(0, |addrs| addrs.len());
+    let (entries, current) = ctx.history.entries(len);
+    for (idx, (expr, hits)) in entries.iter().enumerate() {
+        let line = format!("{:3}: {}\t{} addresses", idx, expr, hits);
+        if idx == current {
+            println!("{}", line.green());
         } else {
-            println!("{:3}: {:x}\t{}\t{}", idx, addr, old_val, new_val.red());
+            println!("{}", line);
         }
     }
 }
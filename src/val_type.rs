@@ -0,0 +1,335 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::mem;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::commands::ValType;
+use crate::memory_reader::{FromLeBytes, MemoryReader, ToLeBytes};
+use crate::pattern::Pattern;
+
+/// Call a generic function with the concrete type behind a `ValType`.
+/// Useful for code that only knows the type at runtime, like frozen
+/// entries, which all keep their values as raw bytes. Types without a
+/// fixed size, like byte patterns and strings, evaluate the `bytes`
+/// expression.
+macro_rules! with_val_type {
+    ($val_type:expr, $func:ident($($arg:expr),*), bytes => $bytes:expr) => {
+        match $val_type {
+            ValType::Aob | ValType::Ascii | ValType::Utf8 | ValType::Utf16 => $bytes,
+            ValType::I128 => $func::<i128>($($arg),*),
+            ValType::U128 => $func::<u128>($($arg),*),
+            ValType::I64 => $func::<i64>($($arg),*),
+            ValType::U64 => $func::<u64>($($arg),*),
+            ValType::I32 => $func::<i32>($($arg),*),
+            ValType::U32 => $func::<u32>($($arg),*),
+            ValType::I16 => $func::<i16>($($arg),*),
+            ValType::U16 => $func::<u16>($($arg),*),
+            ValType::I8 => $func::<i8>($($arg),*),
+            ValType::U8 => $func::<u8>($($arg),*),
+            ValType::F64 => $func::<f64>($($arg),*),
+            ValType::F32 => $func::<f32>($($arg),*),
+        }
+    };
+}
+pub(crate) use with_val_type;
+
+fn size<T>() -> usize {
+    mem::size_of::<T>()
+}
+
+fn parse_bytes<T>(value: &str) -> Result<Vec<u8>, String>
+where
+    T: FromStr + ToLeBytes,
+    T::Err: Debug,
+{
+    value
+        .parse::<T>()
+        .map(T::to_le_bytes)
+        .map_err(|e| format!("couldn't parse {:?}: {:?}", value, e))
+}
+
+fn write<T>(memory_reader: &mut impl MemoryReader, addr: usize, value: &str) -> Result<(), String>
+where
+    T: FromStr + ToLeBytes,
+    T::Err: Debug,
+{
+    let value = value
+        .parse::<T>()
+        .map_err(|e| format!("couldn't parse {:?}: {:?}", value, e))?;
+    memory_reader.write(addr, value).map_err(|e| e.to_string())
+}
+
+fn format_bytes<T: FromLeBytes + ToString>(bytes: &[u8]) -> String {
+    T::from_le_bytes(bytes).to_string()
+}
+
+fn cmp_bytes<T: FromLeBytes + PartialOrd>(lhs: &[u8], rhs: &[u8]) -> Option<Ordering> {
+    T::from_le_bytes(lhs).partial_cmp(&T::from_le_bytes(rhs))
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    Pattern::exact(bytes.to_vec()).to_string()
+}
+
+impl ValType {
+    /// Lowercase name, the same one used on the command line
+    pub fn name(&self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
+    }
+
+    /// Size of the values, None for types like byte arrays that can
+    /// be of any length
+    pub fn size(&self) -> Option<usize> {
+        Some(with_val_type!(self, size(), bytes => return None))
+    }
+
+    /// Whether values are strings in one of the text encodings
+    pub fn is_text(&self) -> bool {
+        matches!(self, ValType::Ascii | ValType::Utf8 | ValType::Utf16)
+    }
+
+    /// Size of a code unit of strings, which is also the size of
+    /// their terminator
+    fn unit_size(&self) -> usize {
+        match self {
+            ValType::Utf16 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Pattern that values of this type are searched for with, in hex
+    /// like `48 8B ?? 0?`. Strings are turned into one by
+    /// `text_pattern` first. Numbers don't have any, they're compared
+    /// with the filter operators.
+    pub fn parse_pattern(&self, value: &str) -> Result<Pattern, String> {
+        match self {
+            ValType::Aob | ValType::Ascii | ValType::Utf8 | ValType::Utf16 => value.parse(),
+            _ => Err(format!("{} values aren't searched for with patterns", self.name())),
+        }
+    }
+
+    /// Encode text in our string encoding, without a terminator
+    fn encode_text(&self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            ValType::Ascii if !text.is_ascii() => Err(format!("{:?} isn't ASCII", text)),
+            ValType::Ascii | ValType::Utf8 => Ok(text.as_bytes().to_vec()),
+            ValType::Utf16 => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            _ => Err(format!("{} values aren't strings", self.name())),
+        }
+    }
+
+    /// Pattern matching text in our string encoding. Unless it's only
+    /// a prefix, the string has to end with a terminator right after
+    /// the text. Ignoring the case only works for ASCII letters.
+    pub fn text_pattern(
+        &self,
+        text: &str,
+        ignore_case: bool,
+        prefix: bool,
+    ) -> Result<Pattern, String> {
+        let mut bytes = self.encode_text(text)?;
+        let mut masks = vec![0xff; bytes.len()];
+        if ignore_case {
+            // The low byte of UTF-16 code units comes first. Only units
+            // whose high byte is 0 are ASCII letters, others like U+0141
+            // stay exact, and so does the high byte
+            let unit = self.unit_size();
+            for (chunk, mask) in bytes.chunks_exact_mut(unit).zip(masks.chunks_exact_mut(unit)) {
+                if chunk[0].is_ascii_alphabetic() && chunk[1..].iter().all(|&byte| byte == 0) {
+                    chunk[0] &= !0x20;
+                    mask[0] = !0x20;
+                }
+            }
+        }
+        if !prefix {
+            bytes.resize(bytes.len() + self.unit_size(), 0);
+            masks.resize(bytes.len(), 0xff);
+        }
+        Ok(Pattern::masked(bytes, masks))
+    }
+
+    /// Length in bytes of the string at the start of bytes, up to its
+    /// terminator or the end of bytes
+    fn text_len(&self, bytes: &[u8]) -> usize {
+        let unit = self.unit_size();
+        bytes
+            .chunks_exact(unit)
+            .position(|chunk| chunk.iter().all(|&byte| byte == 0))
+            .map_or(bytes.len() / unit * unit, |units| units * unit)
+    }
+
+    fn decode_text(&self, bytes: &[u8]) -> String {
+        let bytes = &bytes[..self.text_len(bytes)];
+        match self {
+            ValType::Utf16 => {
+                let units: Vec<_> = bytes
+                    .chunks_exact(2)
+                    .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            _ => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+
+    /// Overwrite the string at addr with text. It can't be longer
+    /// than the string that's there, a shorter one gets terminated.
+    fn write_text<U: MemoryReader>(
+        &self,
+        memory_reader: &mut U,
+        addr: usize,
+        text: &str,
+    ) -> Result<(), String> {
+        let mut bytes = self.encode_text(text)?;
+        let unit = self.unit_size();
+        let mut current = vec![0; bytes.len() + unit];
+        // The string could end right at the end of readable memory
+        if memory_reader.read_bytes(addr, &mut current).is_err() {
+            current.truncate(bytes.len());
+            memory_reader
+                .read_bytes(addr, &mut current)
+                .map_err(|e| e.to_string())?;
+        }
+        let current_len = self.text_len(&current);
+        if current_len < bytes.len() {
+            return Err(format!(
+                "{:?} doesn't fit in the current string of {} bytes",
+                text, current_len
+            ));
+        }
+        if current_len > bytes.len() {
+            bytes.resize(bytes.len() + unit, 0);
+        }
+        memory_reader
+            .write_bytes(addr, &bytes)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Encode text to take the place of a string of size bytes. Like
+    /// with `write_text` it can't be longer, a shorter one gets
+    /// terminated.
+    pub fn fit_text(&self, text: &str, size: usize) -> Result<Vec<u8>, String> {
+        let mut bytes = self.encode_text(text)?;
+        if bytes.len() > size {
+            return Err(format!("{:?} doesn't fit in {} bytes", text, size));
+        }
+        if bytes.len() + self.unit_size() <= size {
+            bytes.resize(bytes.len() + self.unit_size(), 0);
+        }
+        Ok(bytes)
+    }
+
+    pub fn parse_bytes(&self, value: &str) -> Result<Vec<u8>, String> {
+        with_val_type!(self, parse_bytes(value), bytes => {
+            if self.is_text() {
+                return self.encode_text(value);
+            }
+            let pattern = self.parse_pattern(value)?;
+            pattern
+                .exact_bytes()
+                .map(|bytes| bytes.to_vec())
+                .ok_or(format!("{:?} can't contain wildcards", value))
+        })
+    }
+
+    /// Parse value as this type and write it to addr
+    pub fn write<U: MemoryReader>(
+        &self,
+        memory_reader: &mut U,
+        addr: usize,
+        value: &str,
+    ) -> Result<(), String> {
+        with_val_type!(self, write(memory_reader, addr, value), bytes => {
+            if self.is_text() {
+                return self.write_text(memory_reader, addr, value);
+            }
+            let bytes = self.parse_bytes(value)?;
+            memory_reader
+                .write_bytes(addr, &bytes)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    /// `bytes` has to be at least `self.size()` long. Strings end at
+    /// their terminator
+    pub fn format_bytes(&self, bytes: &[u8]) -> String {
+        with_val_type!(self, format_bytes(bytes), bytes => match self.is_text() {
+            true => format!("{:?}", self.decode_text(bytes)),
+            false => format_hex(bytes),
+        })
+    }
+
+    /// Like `format_bytes`, but strings aren't quoted, so the value
+    /// can be parsed again with `parse_bytes`
+    pub fn format_value(&self, bytes: &[u8]) -> String {
+        match self.is_text() {
+            true => self.decode_text(bytes),
+            false => self.format_bytes(bytes),
+        }
+    }
+
+    /// Compare two values, both at least `self.size()` bytes long.
+    /// Byte arrays and strings compare lexicographically.
+    pub fn cmp_bytes(&self, lhs: &[u8], rhs: &[u8]) -> Option<Ordering> {
+        with_val_type!(self, cmp_bytes(lhs, rhs), bytes => Some(lhs.cmp(rhs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn val_type_bytes_round_trip() {
+        let bytes = ValType::I16.parse_bytes("-2").unwrap();
+        assert_eq!(bytes, vec![0xfe, 0xff]);
+        assert_eq!(ValType::I16.format_bytes(&bytes), "-2");
+        assert!(ValType::U8.parse_bytes("256").is_err());
+
+        let lhs = ValType::F32.parse_bytes("1.5").unwrap();
+        let rhs = ValType::F32.parse_bytes("-3").unwrap();
+        assert_eq!(ValType::F32.cmp_bytes(&lhs, &rhs), Some(Ordering::Greater));
+        assert_eq!(ValType::U128.size(), Some(16));
+        assert_eq!(ValType::F64.name(), "f64");
+    }
+
+    #[test]
+    fn val_type_aob() {
+        assert_eq!(ValType::Aob.size(), None);
+        let bytes = ValType::Aob.parse_bytes("0f 1e fa").unwrap();
+        assert_eq!(bytes, vec![0x0f, 0x1e, 0xfa]);
+        assert_eq!(ValType::Aob.format_bytes(&bytes), "0F 1E FA");
+        assert!(ValType::Aob.parse_bytes("0f ??").is_err());
+        assert!(ValType::U8.parse_pattern("0f").is_err());
+        let lhs = [1, 2];
+        assert_eq!(ValType::Aob.cmp_bytes(&lhs, &[1, 3]), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn val_type_text() {
+        assert_eq!(ValType::Utf16.size(), None);
+        assert_eq!(ValType::Utf16.parse_bytes("hé").unwrap(), vec![b'h', 0, 0xe9, 0]);
+        assert!(ValType::Ascii.parse_bytes("hé").is_err());
+        assert_eq!(ValType::Utf8.format_bytes(b"hi\0there"), "\"hi\"");
+        assert_eq!(ValType::Utf16.format_bytes(&[b'h', 0, b'i', 0, 0]), "\"hi\"");
+
+        let exact = ValType::Ascii.text_pattern("Hi", false, false).unwrap();
+        assert!(exact.matches(b"Hi\0"));
+        assert!(!exact.matches(b"Hi!"));
+        let prefix = ValType::Utf16.text_pattern("Hi", true, true).unwrap();
+        assert_eq!(prefix.len(), 4);
+        assert!(prefix.matches(&[b'h', 0, b'I', 0]));
+        assert!(!prefix.matches(&[b'h', 1, b'I', 0]));
+        // Ł is U+0141, š is U+0161
+        let non_ascii = ValType::Utf16.text_pattern("Ł", true, true).unwrap();
+        assert!(non_ascii.matches(&[0x41, 0x01]));
+        assert!(!non_ascii.matches(&[0x61, 0x01]));
+
+        assert_eq!(ValType::Utf8.fit_text("Bob", 4).unwrap(), b"Bob\0");
+        assert_eq!(ValType::Utf8.fit_text("Bob", 3).unwrap(), b"Bob");
+        assert_eq!(ValType::Utf16.fit_text("Al", 16).unwrap(), vec![b'A', 0, b'l', 0, 0, 0]);
+        assert!(ValType::Utf8.fit_text("Bobby", 4).is_err());
+    }
+}